@@ -0,0 +1,60 @@
+//! Compares merging sorted files against the old way of appending every
+//! file and sorting everything again. Run with `cargo bench`.
+
+use std::time::{Duration, Instant};
+
+use mergevtt::{Timerange, Timestamp, WebVTT, WebVTTCue};
+
+const FILES: u64 = 8;
+const CUES: u64 = 2_000;
+
+/// Interleaving cues of `FILES` speakers, every file sorted on its own
+fn files() -> Vec<WebVTT> {
+    (0..FILES).map(|file| {
+        let mut vtt = WebVTT::new();
+        for i in 0..CUES {
+            let start = Timestamp::from_millis(i * 1000 * FILES + file * 700);
+            let range = Timerange::new(start, start + Duration::from_millis(900));
+            vtt.push(WebVTTCue::new(range, Some(format!("Speaker {file}")), format!("cue {i}")));
+        }
+        vtt
+    }).collect()
+}
+
+/// Runs `f` a few times on fresh input and reports the fastest run
+fn bench(name: &str, f: impl Fn(Vec<WebVTT>) -> WebVTT) -> WebVTT {
+    let mut best = Duration::MAX;
+    let mut merged = WebVTT::new();
+    for _ in 0..10 {
+        let files = files();
+        let start = Instant::now();
+        merged = f(files);
+        best = best.min(start.elapsed());
+    }
+    println!("{name:<12} {:>10.3?}", best);
+    merged
+}
+
+fn main() {
+    println!("{FILES} files of {CUES} cues");
+    let sorted = bench("append+sort", |files| {
+        let mut merged = WebVTT::new();
+        for file in files {
+            for cue in file.cues() {
+                merged.push(cue.clone());
+            }
+            merged.sort();
+        }
+        merged
+    });
+    let pairwise = bench("merge_with", |files| {
+        let mut merged = WebVTT::new();
+        for file in files {
+            merged.merge_with(file);
+        }
+        merged
+    });
+    let all = bench("merge_all", WebVTT::merge_all);
+    assert_eq!(pairwise, sorted);
+    assert_eq!(all, sorted);
+}
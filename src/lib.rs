@@ -0,0 +1,2381 @@
+//! Parse, merge, and print WebVTT subtitle files.
+//!
+//! ```
+//! use mergevtt::WebVTT;
+//!
+//! let mut alice = WebVTT::from("WEBVTT\n\n00:00:01.000 --> 00:00:02.000\nHi Bob\n").unwrap();
+//! let mut bob = WebVTT::from("WEBVTT\n\n00:00:01.500 --> 00:00:03.000\nHi Alice\n").unwrap();
+//! alice.set_speaker_for_all_lines("Alice");
+//! bob.set_speaker_for_all_lines("Bob");
+//! alice.merge_with(bob);
+//!
+//! assert_eq!(alice.to_string(), "\
+//! WEBVTT
+//!
+//! 00:00:01.000 --> 00:00:02.000
+//! <v Alice>Hi Bob
+//!
+//! 00:00:01.500 --> 00:00:03.000
+//! <v Bob>Hi Alice
+//! ");
+//! ```
+
+use std::{fmt::Display, path::Path, time::Duration};
+
+/// Everything that can go wrong while loading a file, so that callers can
+/// tell a missing file from a broken one.
+///
+/// ```
+/// # use mergevtt::{Error, WebVTT};
+/// assert!(matches!(WebVTT::from_path("does/not/exist.vtt"), Err(Error::Io(_))));
+/// ```
+#[derive(Debug, thiserror::Error)]
+pub enum Error {
+    #[error(transparent)]
+    Io(std::io::Error),
+    #[error("not valid UTF-8: {0}")]
+    Encoding(#[from] std::string::FromUtf8Error),
+    #[error("not valid {0}")]
+    Decoding(&'static str),
+    #[error(transparent)]
+    Parse(WebVTTError),
+    #[error("unsupported: {0}")]
+    Unsupported(&'static str),
+}
+impl From<std::io::Error> for Error {
+    fn from(e: std::io::Error) -> Self {
+        Self::Io(e)
+    }
+}
+impl From<WebVTTError> for Error {
+    fn from(e: WebVTTError) -> Self {
+        match e {
+            // reading can fail halfway, that's still not a parse error
+            WebVTTError::Io(e) => Self::Io(e),
+            e => Self::Parse(e),
+        }
+    }
+}
+
+/// Parse errors say which line of the input they are about.
+///
+/// ```
+/// # use mergevtt::WebVTT;
+/// let input = "WEBVTT\n\n00:01.000 --> 00:02.000\nfine\n\n00:03.000 --> 00:0x.000\nbroken\n";
+/// let error = WebVTT::from(input).unwrap_err();
+/// assert!(error.to_string().starts_with("line 6: "), "{error}");
+/// ```
+#[derive(Debug, thiserror::Error)]
+pub enum WebVTTError {
+    #[error(transparent)]
+    Io(#[from] std::io::Error),
+    #[error("parsing error: expected {0}, got '{1}'")]
+    Parsing(String, String),
+    #[error("line {0}: {1}")]
+    Line(usize, Box<WebVTTError>),
+    #[error("cue ends before it starts: {0}")]
+    EndBeforeStart(Timerange),
+    #[error("scale factor must be positive, got {0}")]
+    InvalidScale(f64),
+    #[error("shifting {0} back by {1:?} would make it negative")]
+    NegativeTimestamp(Timestamp, Duration),
+    #[error("empty {0} in timestamp '{1}'")]
+    EmptyComponent(&'static str, String),
+    #[cfg(feature = "serde")]
+    #[error("invalid JSON: {0}")]
+    Json(#[from] serde_json::Error),
+}
+impl WebVTTError {
+    fn at_line(self, line: usize) -> Self {
+        Self::Line(line, Box::new(self))
+    }
+}
+
+/// Deviations from the spec that [`WebVTT::parse`] should accept.
+///
+/// ```
+/// # use mergevtt::{ParseOptions, WebVTT};
+/// let indented = "  WEBVTT\n\n00:01.000 --> 00:02.000\nhi\n";
+/// assert!(WebVTT::from(indented).is_err());
+/// let options = ParseOptions { lenient_header: true, ..ParseOptions::default() };
+/// assert_eq!(WebVTT::parse(indented, &options).unwrap().len(), 1);
+///
+/// let cramped = "WEBVTT\n00:01.000 --> 00:02.000\nhi\n";
+/// assert_eq!(
+///     WebVTT::from(cramped).unwrap_err().to_string(),
+///     "line 2: parsing error: expected a blank line after the header, got '00:01.000 --> 00:02.000'",
+/// );
+/// let options = ParseOptions { allow_missing_blank_line: true, ..ParseOptions::default() };
+/// let vtt = WebVTT::parse(cramped, &options).unwrap();
+/// assert_eq!(vtt.to_string(), "WEBVTT\n\n00:01.000 --> 00:02.000\nhi\n");
+/// ```
+#[derive(Debug, Clone, Default)]
+pub struct ParseOptions {
+    /// Accept a cue right after the header, without a blank line in between
+    pub allow_missing_blank_line: bool,
+    /// Accept whitespace before `WEBVTT`
+    pub lenient_header: bool,
+    /// Read `HH:MM:SS:mmm`, with a colon before the milliseconds
+    pub lenient_timestamps: bool,
+}
+
+/// What [`WebVTT::sort_by`] orders the cues by first.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum SortKey {
+    /// The natural order, see [`WebVTT::sort`]
+    #[default]
+    Start,
+    End,
+    /// Cues without a speaker first
+    Speaker,
+}
+
+/// How the output of a [`WebVTT`] should look, see [`WebVTT::set_render_options`].
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct RenderOptions {
+    pub voice_style: VoiceStyle,
+    /// Write a `NOTE` with the [`WebVTTCue::source`] before every cue
+    pub annotate_source: bool,
+    pub precision: Precision,
+    /// Print cue timings as they were written until they change. This
+    /// keeps diffs small, but the input may not be valid WebVTT
+    pub keep_timing: bool,
+}
+
+/// How many digits of the seconds cue times are written with, rounded to
+/// the nearest value.
+///
+/// WebVTT needs exactly three digits after the point, so players that
+/// follow the spec closely may reject files written with anything but
+/// [`Precision::Milliseconds`].
+///
+/// ```
+/// # use mergevtt::{Precision, RenderOptions, WebVTT};
+/// let mut vtt = WebVTT::from("WEBVTT\n\n00:01.234 --> 59:59.995\nhi\n").unwrap();
+/// vtt.set_render_options(RenderOptions { precision: Precision::Centiseconds, ..RenderOptions::default() });
+/// assert!(vtt.to_string().ends_with("\n00:01.23 --> 01:00:00.00\nhi\n"));
+/// vtt.set_render_options(RenderOptions { precision: Precision::Seconds, ..RenderOptions::default() });
+/// assert!(vtt.to_string().ends_with("\n00:01 --> 01:00:00\nhi\n"));
+/// ```
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum Precision {
+    /// `00:01.234`, as the input is read
+    #[default]
+    Milliseconds,
+    /// `00:01.23`, not valid WebVTT
+    Centiseconds,
+    /// `00:01`, not valid WebVTT
+    Seconds,
+}
+
+/// How a cue's speaker is written out.
+///
+/// ```
+/// # use mergevtt::{RenderOptions, VoiceStyle, WebVTT};
+/// let mut vtt = WebVTT::from("WEBVTT\n\n00:01.000 --> 00:02.000\n<v Bob>hi\n").unwrap();
+/// assert!(vtt.to_string().ends_with("\n<v Bob>hi\n"));
+/// vtt.set_render_options(RenderOptions { voice_style: VoiceStyle::Class("loud".into()), ..RenderOptions::default() });
+/// assert!(vtt.to_string().ends_with("\n<v.loud Bob>hi\n"));
+/// vtt.set_render_options(RenderOptions { voice_style: VoiceStyle::Plain, ..RenderOptions::default() });
+/// assert!(vtt.to_string().ends_with("\nBob: hi\n"));
+/// ```
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub enum VoiceStyle {
+    /// `<v Speaker>`
+    #[default]
+    Tag,
+    /// `<v.class Speaker>`
+    Class(String),
+    /// `Speaker: `
+    Plain,
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize), serde(default))]
+pub struct WebVTT {
+    /// Everything after `WEBVTT` up to the first blank line
+    header: String,
+    styles: Vec<String>,
+    regions: Vec<String>,
+    /// Notes that aren't followed by a cue
+    notes: Vec<String>,
+    cues: Vec<WebVTTCue>,
+    #[cfg_attr(feature = "serde", serde(skip))]
+    render_options: RenderOptions,
+}
+impl WebVTT {
+    pub fn new() -> Self { Self::default() }
+    /// Sets a `Name: value` metadata line in the header, replacing one with
+    /// the same name.
+    ///
+    /// ```
+    /// # use mergevtt::WebVTT;
+    /// let mut vtt = WebVTT::from("WEBVTT\nKind: subtitles\n\n00:01.000 --> 00:02.000\nhi\n").unwrap();
+    /// vtt.set_header_field("Language", "en");
+    /// vtt.set_header_field("Kind", "captions");
+    /// assert!(vtt.to_string().starts_with("WEBVTT\nKind: captions\nLanguage: en\n\n"));
+    /// ```
+    pub fn set_header_field(&mut self, name: &str, value: &str) {
+        let field = format!("{name}: {value}");
+        let mut lines: Vec<&str> = self.header.split('\n').collect();
+        // the first line is what follows `WEBVTT` on its own line
+        match lines.iter().skip(1).position(|l| l.split_once(':').is_some_and(|(n, _)| n.trim() == name)) {
+            Some(i) => lines[i + 1] = &field,
+            None => lines.push(&field),
+        }
+        self.header = lines.join("\n");
+    }
+    /// Changes how [`Display`] and [`WebVTT::write_to`] format the cues.
+    pub fn set_render_options(&mut self, options: RenderOptions) {
+        self.render_options = options;
+    }
+    pub fn cues(&self) -> impl Iterator<Item = &WebVTTCue> {
+        self.cues.iter()
+    }
+    pub fn cues_mut(&mut self) -> impl Iterator<Item = &mut WebVTTCue> {
+        self.cues.iter_mut()
+    }
+    pub fn len(&self) -> usize {
+        self.cues.len()
+    }
+    /// A file can be just the header, merging those gives just the header
+    /// again.
+    ///
+    /// ```
+    /// # use mergevtt::WebVTT;
+    /// let vtt = WebVTT::from("WEBVTT").unwrap();
+    /// assert!(vtt.is_empty());
+    /// assert_eq!(vtt.to_string(), "WEBVTT\n");
+    /// let merged = WebVTT::merge_all(vec![vtt.clone(), vtt]);
+    /// assert_eq!(merged.to_string(), "WEBVTT\n");
+    /// ```
+    pub fn is_empty(&self) -> bool {
+        self.cues.is_empty()
+    }
+    /// Appends a cue, call [`WebVTT::sort`] afterwards if it's out of order.
+    ///
+    /// ```
+    /// # use mergevtt::{Timerange, Timestamp, WebVTT, WebVTTCue};
+    /// let mut vtt = WebVTT::new();
+    /// vtt.push(WebVTTCue::new(
+    ///     Timerange::new(Timestamp::from_millis(0), Timestamp::from_millis(1500)),
+    ///     Some("Alice".to_owned()),
+    ///     "Hello".to_owned(),
+    /// ));
+    /// vtt.push(WebVTTCue::new(
+    ///     Timerange::new(Timestamp::from_millis(2000), Timestamp::from_millis(3000)),
+    ///     None,
+    ///     "[applause]".to_owned(),
+    /// ));
+    /// assert_eq!(vtt.to_string(), "\
+    /// WEBVTT
+    ///
+    /// 00:00:00.000 --> 00:00:01.500
+    /// <v Alice>Hello
+    ///
+    /// 00:00:02.000 --> 00:00:03.000
+    /// [applause]
+    /// ");
+    /// ```
+    pub fn push(&mut self, cue: WebVTTCue) {
+        self.cues.push(cue);
+    }
+    /// Parses a WebVTT file. Lines may end in LF, CRLF, or CR, and a leading
+    /// byte order mark is ignored.
+    ///
+    /// Only an empty line ends a block. A line of just whitespace is part of
+    /// the cue text it appears in, and skipped between blocks.
+    ///
+    /// The first line of a block that contains `-->` is the cue timing,
+    /// otherwise the first line is the identifier and the second the timing.
+    /// After the timing every line is cue text, even if it contains `-->`.
+    ///
+    /// ```
+    /// # use mergevtt::WebVTT;
+    /// let crlf = WebVTT::from("WEBVTT\r\n\r\n00:01.000 --> 00:02.000\r\nhi\r\n").unwrap();
+    /// let lf = WebVTT::from("WEBVTT\n\n00:01.000 --> 00:02.000\nhi\n").unwrap();
+    /// assert_eq!(crlf, lf);
+    /// assert_eq!(WebVTT::from("\u{feff}WEBVTT\n\n00:01.000 --> 00:02.000\nhi\n").unwrap(), lf);
+    /// assert_eq!(crlf.to_string(), "WEBVTT\n\n00:01.000 --> 00:02.000\nhi\n");
+    ///
+    /// let spaced = WebVTT::from("WEBVTT\n\n00:01.000 --> 00:02.000\nhi\n  \nthere\n\n  \n").unwrap();
+    /// assert_eq!(spaced.to_string(), "WEBVTT\n\n00:01.000 --> 00:02.000\nhi\n  \nthere\n");
+    /// ```
+    ///
+    /// Malformed input is always an error, never a panic:
+    ///
+    /// ```
+    /// # use mergevtt::WebVTT;
+    /// let file = "WEBVTT\n\nNOTE x\n\nid\n00:01.000 --> 01:00:02.5 align:start\n<v A&amp;B>hi\n";
+    /// for end in (0..=file.len()).filter(|&end| file.is_char_boundary(end)) {
+    ///     let _ = WebVTT::from(&file[..end]);
+    /// }
+    /// // every timing line of up to four characters from a nasty alphabet
+    /// let alphabet = ['0', '9', ':', '.', ',', ' ', '-', '>'];
+    /// let mut timing = String::new();
+    /// for n in 0..8usize.pow(4) {
+    ///     timing.clear();
+    ///     let mut n = n;
+    ///     while n > 0 {
+    ///         timing.push(alphabet[n % 8]);
+    ///         n /= 8;
+    ///     }
+    ///     let _ = WebVTT::from(&format!("WEBVTT\n\n{timing} --> {timing}\nhi\n"));
+    /// }
+    /// let huge = "18446744073709551615:00:00.000";
+    /// assert!(WebVTT::from(&format!("WEBVTT\n\n{huge} --> {huge}\nhi\n")).is_err());
+    /// assert!(WebVTT::from("WEBVTT\n\n1:2:3:4.000 --> 1:2:3:5.000\nhi\n").is_err());
+    /// ```
+    ///
+    /// Timings are printed the canonical way, or with
+    /// [`RenderOptions::keep_timing`] exactly as they were written until
+    /// they change.
+    ///
+    /// ```
+    /// # use mergevtt::{RenderOptions, WebVTT};
+    /// # use std::time::Duration;
+    /// let mut vtt = WebVTT::from("WEBVTT\n\n00:01.00 -->  00:02.00\nhi\n").unwrap();
+    /// assert_eq!(vtt.to_string(), "WEBVTT\n\n00:01.000 --> 00:02.000\nhi\n");
+    /// vtt.set_render_options(RenderOptions { keep_timing: true, ..RenderOptions::default() });
+    /// assert_eq!(vtt.to_string(), "WEBVTT\n\n00:01.00 -->  00:02.00\nhi\n");
+    /// vtt.shift(Duration::from_secs(1), true);
+    /// assert_eq!(vtt.to_string(), "WEBVTT\n\n00:02.000 --> 00:03.000\nhi\n");
+    /// ```
+    pub fn from(string: &str) -> Result<Self, WebVTTError> {
+        Self::parse(string, &ParseOptions::default())
+    }
+    /// Like [`WebVTT::from`], but lets some deviations from the spec pass.
+    pub fn parse(string: &str, options: &ParseOptions) -> Result<Self, WebVTTError> {
+        let string = &normalize(string);
+        let string = match options.lenient_header {
+            true => string.trim_start_matches([' ', '\t']),
+            false => string,
+        };
+        if !string.starts_with("WEBVTT") {
+            return Err(WebVTTError::Parsing(
+                "WEBVTT".to_owned(),
+                string.lines().next().unwrap_or("").to_owned(),
+            ).at_line(1));
+        }
+
+        // a trailing empty line flushes the last block
+        let mut input = string.lines().chain(std::iter::once("")).enumerate().map(|(i, l)| (i + 1, l));
+
+        // the header line can carry a description after `WEBVTT`, followed
+        // by metadata lines up to the first blank line
+        let mut header = input.next().map(|(_, l)| l["WEBVTT".len()..].to_owned()).unwrap_or_default();
+        let mut first_cue = None;
+        for (line_number, line) in input.by_ref() {
+            if line.is_empty() {
+                break;
+            }
+            if line.contains("-->") {
+                if !options.allow_missing_blank_line {
+                    return Err(WebVTTError::Parsing(
+                        "a blank line after the header".to_owned(),
+                        line.to_owned(),
+                    ).at_line(line_number));
+                }
+                first_cue = Some((line_number, line));
+                break;
+            }
+            header.push('\n');
+            header.push_str(line);
+        }
+        let input = first_cue.into_iter().chain(input);
+
+        let mut styles: Vec<String> = vec![];
+        let mut regions: Vec<String> = vec![];
+        let mut notes: Vec<String> = vec![];
+        // notes belong to the cue after them, unless a style or region
+        // block comes first
+        let mut cue_notes: Vec<String> = vec![];
+        let mut lines: Vec<WebVTTCue> = vec![];
+
+        let mut identifier: Option<String> = None;
+        let mut range: Option<Timerange> = None;
+        let mut settings: Option<String> = None;
+        let mut timing: Option<String> = None;
+        let mut cue: Option<WebVTTCue> = None;
+        let mut block: Option<(BlockKind, String)> = None;
+        for (line_number, line) in input {
+            if line.is_empty() {
+                match block.take() {
+                    Some((BlockKind::Note, text)) => cue_notes.push(text),
+                    Some((BlockKind::Style, text)) => {
+                        notes.append(&mut cue_notes);
+                        styles.push(text);
+                    },
+                    Some((BlockKind::Region, text)) => {
+                        notes.append(&mut cue_notes);
+                        regions.push(text);
+                    },
+                    None => {},
+                }
+                lines.extend(cue.take());
+                identifier = None;
+                range = None;
+                settings = None;
+                timing = None;
+                continue;
+            }
+            if line.trim().is_empty() && block.is_none() && identifier.is_none() && range.is_none() {
+                // stray whitespace between blocks
+                continue;
+            }
+            if let Some((_, text)) = &mut block {
+                text.push('\n');
+                text.push_str(line);
+                continue;
+            }
+            match (&range, &mut cue) {
+                (None, None) if identifier.is_none() && BlockKind::from(line).is_some() => {
+                    block = BlockKind::from(line).map(|kind| (kind, line.to_owned()));
+                },
+                (None, _) if identifier.is_none() && !line.contains("-->") => {
+                    identifier = Some(line.to_owned());
+                },
+                (None, _) => {
+                    let (cue_timing, cue_settings) = split_cue_settings(line);
+                    range = Some(Timerange::parse(cue_timing, options).map_err(|e| e.at_line(line_number))?);
+                    settings = cue_settings.map(str::to_owned);
+                    // repaired timestamps are printed the right way
+                    let repaired = cue_timing.split_whitespace().any(|t| t.split(':').count() > 3);
+                    timing = Some(cue_timing.trim().to_owned()).filter(|_| !repaired);
+                },
+                (Some(range), None) => {
+                    let mut new = WebVTTCue::from(identifier.take(), range, line)
+                        .map_err(|e| e.at_line(line_number))?;
+                    new.settings = settings.take();
+                    new.raw_timing = timing.take().map(|raw| (range.clone(), raw));
+                    new.notes = std::mem::take(&mut cue_notes);
+                    cue = Some(new);
+                },
+                (Some(_), Some(cue)) => cue.push_line(line),
+            }
+        }
+        notes.append(&mut cue_notes);
+        Ok(Self { header, styles, regions, notes, cues: lines, ..Self::default() })
+    }
+    /// Reads and parses a WebVTT file. The cues get the path as their [`WebVTTCue::source`].
+    pub fn from_path(path: impl AsRef<Path>) -> Result<Self, Error> {
+        let path = path.as_ref();
+        let mut vtt = Self::from_reader(std::io::BufReader::new(std::fs::File::open(path)?))?;
+        vtt.set_source(&path.to_string_lossy());
+        Ok(vtt)
+    }
+    /// Parses a WebVTT file from a reader.
+    ///
+    /// For now this reads everything before parsing.
+    ///
+    /// ```
+    /// # use mergevtt::WebVTT;
+    /// let reader = std::io::Cursor::new("WEBVTT\n\n00:01.000 --> 00:02.000\nhi\n");
+    /// assert_eq!(WebVTT::from_reader(reader).unwrap().len(), 1);
+    /// ```
+    pub fn from_reader<R: std::io::BufRead>(reader: R) -> Result<Self, WebVTTError> {
+        Self::from(&std::io::read_to_string(reader)?)
+    }
+    /// Parses a SubRip file.
+    ///
+    /// The cue numbers are checked but not kept, since they would clash when merging.
+    pub fn from_srt(string: &str) -> Result<Self, WebVTTError> {
+        let string = &normalize(string);
+        let mut lines: Vec<WebVTTCue> = vec![];
+
+        let mut number: Option<usize> = None;
+        let mut range: Option<Timerange> = None;
+        let mut cue: Option<WebVTTCue> = None;
+        // a trailing empty line flushes the last cue
+        let input = string.lines().chain(std::iter::once("")).enumerate().map(|(i, l)| (i + 1, l));
+        for (line_number, line) in input {
+            if line.trim().is_empty() {
+                lines.extend(cue.take());
+                number = None;
+                range = None;
+                continue;
+            }
+            match (&range, &mut cue) {
+                (None, _) if number.is_none() && !line.contains("-->") => {
+                    number = Some(line.trim().parse().map_err(|_| {
+                        WebVTTError::Parsing("a cue number".to_owned(), line.to_owned()).at_line(line_number)
+                    })?);
+                },
+                (None, _) => {
+                    let (timing, _) = split_cue_settings(line);
+                    range = Some(Timerange::from(timing).map_err(|e| e.at_line(line_number))?);
+                },
+                (Some(range), None) => {
+                    cue = Some(WebVTTCue::from(None, range, line).map_err(|e| e.at_line(line_number))?);
+                },
+                (Some(_), Some(cue)) => cue.push_line(line),
+            }
+        }
+        Ok(Self { cues: lines, ..Self::default() })
+    }
+    /// Parses either the JSON form of a whole file or a plain array of cues.
+    #[cfg(feature = "serde")]
+    pub fn from_json(string: &str) -> Result<Self, WebVTTError> {
+        if string.trim_start().starts_with('[') {
+            let cues = serde_json::from_str(string)?;
+            return Ok(Self { cues, ..Self::default() });
+        }
+        Ok(serde_json::from_str(string)?)
+    }
+    /// Sorts the cues by their natural order, see the [`Ord`] of
+    /// [`WebVTTCue`]: by start time, then end time, then alphabetically by
+    /// speaker with cues without a speaker first, then by text.
+    ///
+    /// ```
+    /// # use mergevtt::WebVTT;
+    /// let mut vtt = WebVTT::from("\
+    /// WEBVTT
+    ///
+    /// 00:01.000 --> 00:02.000
+    /// <v Bob>hi
+    ///
+    /// 00:01.000 --> 00:02.000
+    /// <v Alice>hello
+    /// ").unwrap();
+    /// vtt.sort();
+    /// assert_eq!(vtt.cues().map(|c| c.speaker().unwrap()).collect::<Vec<_>>(), ["Alice", "Bob"]);
+    /// ```
+    pub fn sort(&mut self) {
+        self.sort_by(SortKey::Start);
+    }
+    /// Sorts the cues by `key`, breaking ties by the natural order.
+    ///
+    /// Merging and most other methods expect the natural order of
+    /// [`WebVTT::sort`], so this is best done last.
+    ///
+    /// ```
+    /// # use mergevtt::{SortKey, WebVTT};
+    /// let mut vtt = WebVTT::from("\
+    /// WEBVTT
+    ///
+    /// 00:01.000 --> 00:04.000
+    /// <v Bob>one
+    ///
+    /// 00:02.000 --> 00:03.000
+    /// <v Alice>two
+    ///
+    /// 00:03.000 --> 00:05.000
+    /// <v Alice>three
+    /// ").unwrap();
+    /// let texts = |vtt: &WebVTT| vtt.cues().map(|c| c.text().to_owned()).collect::<Vec<_>>();
+    /// vtt.sort_by(SortKey::End);
+    /// assert_eq!(texts(&vtt), ["two", "one", "three"]);
+    /// vtt.sort_by(SortKey::Speaker);
+    /// assert_eq!(texts(&vtt), ["two", "three", "one"]);
+    /// vtt.sort_by(SortKey::Start);
+    /// assert_eq!(texts(&vtt), ["one", "two", "three"]);
+    /// ```
+    pub fn sort_by(&mut self, key: SortKey) {
+        match key {
+            SortKey::Start => self.cues.sort(),
+            SortKey::End => self.cues.sort_by(|a, b| a.range.1.cmp(&b.range.1).then_with(|| a.cmp(b))),
+            SortKey::Speaker => self.cues.sort_by(|a, b| a.speaker.cmp(&b.speaker).then_with(|| a.cmp(b))),
+        }
+    }
+    /// Returns the cues showing at `t`, in order. A cue shows from its start
+    /// up to, but not including, its end.
+    ///
+    /// Expects the cues to be sorted. A binary search skips the cues that
+    /// start after `t`, but since cues can overlap, a long one can start
+    /// arbitrarily far before `t`, so all earlier cues are checked. A
+    /// lookup is O(n), only cheaper the earlier `t` is. To follow playback,
+    /// walking [`WebVTT::cues`] along with it is faster.
+    ///
+    /// ```
+    /// # use mergevtt::{Timestamp, WebVTT};
+    /// let vtt = WebVTT::from("\
+    /// WEBVTT
+    ///
+    /// 00:01.000 --> 00:02.000
+    /// one
+    ///
+    /// 00:01.500 --> 00:03.000
+    /// two
+    ///
+    /// 00:02.000 --> 00:04.000
+    /// three
+    /// ").unwrap();
+    /// let at = |ms| vtt.cues_at(Timestamp::from_millis(ms)).iter().map(|c| c.text()).collect::<Vec<_>>();
+    /// assert_eq!(at(1500), ["one", "two"]);
+    /// assert_eq!(at(2000), ["two", "three"]);
+    /// assert_eq!(at(4000), Vec::<&str>::new());
+    /// ```
+    pub fn cues_at(&self, t: Timestamp) -> Vec<&WebVTTCue> {
+        let candidates = self.cues.partition_point(|l| l.range.0 <= t);
+        self.cues[..candidates].iter().filter(|l| l.range.1 > t).collect()
+    }
+    pub fn is_sorted(&self) -> bool {
+        self.first_unsorted().is_none()
+    }
+    /// The first pair of neighbouring cues that are in the wrong order.
+    pub fn first_unsorted(&self) -> Option<(&WebVTTCue, &WebVTTCue)> {
+        self.cues.windows(2)
+            .find(|w| w[0] > w[1])
+            .map(|w| (&w[0], &w[1]))
+    }
+    /// All pairs of neighbouring cues where the second starts before the
+    /// first. Unlike [`WebVTT::first_unsorted`] this ignores cues that
+    /// start together but are ordered differently.
+    ///
+    /// ```
+    /// # use mergevtt::WebVTT;
+    /// let vtt = WebVTT::from("\
+    /// WEBVTT
+    ///
+    /// 00:01.000 --> 00:03.000
+    /// one
+    ///
+    /// 00:01.000 --> 00:02.000
+    /// two
+    ///
+    /// 00:00.500 --> 00:01.000
+    /// three
+    /// ").unwrap();
+    /// assert!(!vtt.is_sorted());
+    /// let backwards: Vec<_> = vtt.backwards_starts().into_iter().map(|(a, b)| (a.text(), b.text())).collect();
+    /// assert_eq!(backwards, [("two", "three")]);
+    /// ```
+    pub fn backwards_starts(&self) -> Vec<(&WebVTTCue, &WebVTTCue)> {
+        self.cues.windows(2)
+            .filter(|w| w[1].range.0 < w[0].range.0)
+            .map(|w| (&w[0], &w[1]))
+            .collect()
+    }
+    /// Records `source`, usually the file name, as where every cue came from.
+    ///
+    /// ```
+    /// # use mergevtt::{RenderOptions, WebVTT};
+    /// let mut vtt = WebVTT::from("WEBVTT\n\n00:01.000 --> 00:02.000\nhi\n").unwrap();
+    /// vtt.set_source("alice.vtt");
+    /// assert_eq!(vtt.to_string(), "WEBVTT\n\n00:01.000 --> 00:02.000\nhi\n");
+    /// vtt.set_render_options(RenderOptions { annotate_source: true, ..RenderOptions::default() });
+    /// assert_eq!(vtt.to_string(), "WEBVTT\n\nNOTE source: alice.vtt\n\n00:01.000 --> 00:02.000\nhi\n");
+    /// ```
+    pub fn set_source(&mut self, source: &str) {
+        for l in self.cues.iter_mut() {
+            l.source = Some(source.to_owned());
+        }
+    }
+    /// Assigns `speaker` to every cue that doesn't already have one.
+    pub fn set_speaker_for_all_lines(&mut self, speaker: &str) {
+        for l in self.cues.iter_mut().filter(|l| l.speaker.is_none()) {
+            l.speaker = Some(speaker.to_owned());
+        }
+    }
+    /// Assigns `speaker` to every cue, replacing voices from the input.
+    pub fn force_speaker_for_all_lines(&mut self, speaker: &str) {
+        for l in self.cues.iter_mut() {
+            l.speaker = Some(speaker.to_owned());
+        }
+    }
+    /// Renames speakers that are just a number, like the labels of a
+    /// diarizer, by putting the number into `template` in place of `{n}`.
+    /// `{n:03}` pads the number with zeros to three digits.
+    ///
+    /// ```
+    /// # use mergevtt::WebVTT;
+    /// let mut vtt = WebVTT::from("WEBVTT\n\n00:01.000 --> 00:02.000\n<v 1>hi\n\n00:02.000 --> 00:03.000\n<v Bob>hey\n").unwrap();
+    /// vtt.apply_speaker_template("Speaker {n:02}").unwrap();
+    /// assert_eq!(vtt.speakers(), ["Bob", "Speaker 01"]);
+    /// assert!(vtt.apply_speaker_template("Speaker").is_err());
+    /// ```
+    pub fn apply_speaker_template(&mut self, template: &str) -> Result<(), WebVTTError> {
+        let invalid = || WebVTTError::Parsing("a template with {n} or {n:0N}".to_owned(), template.to_owned());
+        let (before, rest) = template.split_once("{n").ok_or_else(invalid)?;
+        let (spec, after) = rest.split_once('}').ok_or_else(invalid)?;
+        let width: usize = match spec {
+            "" => 0,
+            spec => spec.strip_prefix(":0").and_then(|w| w.parse().ok()).ok_or_else(invalid)?,
+        };
+        for l in self.cues.iter_mut() {
+            if let Some(n) = l.speaker.as_deref().and_then(|s| s.parse::<u64>().ok()) {
+                l.speaker = Some(format!("{before}{n:0width$}{after}"));
+            }
+        }
+        Ok(())
+    }
+    /// Joins neighbouring cues of the same speaker if the gap between them is
+    /// shorter than `max_gap`. The joined cue spans both and their texts are
+    /// separated by a space.
+    ///
+    /// Expects the cues to be sorted.
+    ///
+    /// ```
+    /// # use std::time::Duration;
+    /// # use mergevtt::WebVTT;
+    /// let mut vtt = WebVTT::from("\
+    /// WEBVTT
+    ///
+    /// 00:01.000 --> 00:02.000
+    /// <v Alice>one
+    ///
+    /// 00:02.099 --> 00:03.000
+    /// <v Alice>two
+    ///
+    /// 00:03.100 --> 00:04.000
+    /// <v Alice>three
+    /// ").unwrap();
+    /// vtt.coalesce(Duration::from_millis(100));
+    /// assert_eq!(vtt.to_string(), "\
+    /// WEBVTT
+    ///
+    /// 00:01.000 --> 00:03.000
+    /// <v Alice>one two
+    ///
+    /// 00:03.100 --> 00:04.000
+    /// <v Alice>three
+    /// ");
+    /// ```
+    pub fn coalesce(&mut self, max_gap: Duration) {
+        self.join_neighbours(|last, cue| {
+            last.speaker == cue.speaker && cue.range.0.0.saturating_sub(last.range.1.0) < max_gap
+        }, |last, cue| {
+            last.text.push(' ');
+            last.text.push_str(&cue.text);
+        });
+    }
+    /// Joins neighbouring cues of the same speaker with the same text into
+    /// one spanning both.
+    ///
+    /// Expects the cues to be sorted.
+    ///
+    /// ```
+    /// # use mergevtt::WebVTT;
+    /// let mut vtt = WebVTT::from("\
+    /// WEBVTT
+    ///
+    /// 00:01.000 --> 00:02.000
+    /// <v Alice>uh
+    ///
+    /// 00:02.000 --> 00:03.000
+    /// <v Alice>uh
+    ///
+    /// 00:03.500 --> 00:04.000
+    /// <v Alice>uh
+    /// ").unwrap();
+    /// vtt.collapse_repeats();
+    /// assert_eq!(vtt.to_string(), "WEBVTT\n\n00:01.000 --> 00:04.000\n<v Alice>uh\n");
+    /// ```
+    pub fn collapse_repeats(&mut self) {
+        self.join_neighbours(|last, cue| last.speaker == cue.speaker && last.text == cue.text, |_, _| {});
+    }
+    /// Extends the previous cue over the next one if `joins` says so, and
+    /// lets `join_text` combine their texts.
+    fn join_neighbours(
+        &mut self,
+        joins: impl Fn(&WebVTTCue, &WebVTTCue) -> bool,
+        join_text: impl Fn(&mut WebVTTCue, &WebVTTCue),
+    ) {
+        let mut cues: Vec<WebVTTCue> = Vec::with_capacity(self.cues.len());
+        for cue in self.cues.drain(..) {
+            match cues.last_mut() {
+                Some(last) if joins(last, &cue) => {
+                    last.range.1 = last.range.1.max(cue.range.1);
+                    last.notes.extend(cue.notes.iter().cloned());
+                    join_text(last, &cue);
+                },
+                _ => cues.push(cue),
+            }
+        }
+        self.cues = cues;
+    }
+    /// Ends each cue no later than the start of the same speaker's next cue,
+    /// and returns how many were cut short. Overlaps between different
+    /// speakers are left alone.
+    ///
+    /// Expects the cues to be sorted.
+    ///
+    /// ```
+    /// # use mergevtt::WebVTT;
+    /// let mut vtt = WebVTT::from("\
+    /// WEBVTT
+    ///
+    /// 00:01.000 --> 00:03.000
+    /// <v Alice>one
+    ///
+    /// 00:02.000 --> 00:04.000
+    /// <v Bob>two
+    ///
+    /// 00:02.500 --> 00:05.000
+    /// <v Alice>three
+    /// ").unwrap();
+    /// assert_eq!(vtt.clamp_overlaps(), 1);
+    /// assert_eq!(vtt.to_string(), "\
+    /// WEBVTT
+    ///
+    /// 00:01.000 --> 00:02.500
+    /// <v Alice>one
+    ///
+    /// 00:02.000 --> 00:04.000
+    /// <v Bob>two
+    ///
+    /// 00:02.500 --> 00:05.000
+    /// <v Alice>three
+    /// ");
+    /// ```
+    pub fn clamp_overlaps(&mut self) -> usize {
+        let mut clamped = 0;
+        let mut previous: std::collections::HashMap<Option<String>, usize> = Default::default();
+        for i in 0..self.cues.len() {
+            let start = self.cues[i].range.0;
+            if let Some(&p) = previous.get(&self.cues[i].speaker) {
+                let end = &mut self.cues[p].range.1;
+                if *end > start {
+                    // keep the end's format, only move it
+                    end.0 = start.0;
+                    clamped += 1;
+                }
+            }
+            previous.insert(self.cues[i].speaker.clone(), i);
+        }
+        clamped
+    }
+    /// Trims cues so that they don't overlap cues of speakers earlier in
+    /// `priority`, and returns how many were trimmed or dropped. Speakers
+    /// not in `priority`, and cues without one, come after all others.
+    ///
+    /// A cue that starts before the one it overlaps ends where that one
+    /// starts, losing anything after it, too. One that starts inside it
+    /// starts where it ends instead, or is dropped if nothing is left.
+    ///
+    /// ```
+    /// # use mergevtt::WebVTT;
+    /// let mut vtt = WebVTT::from("\
+    /// WEBVTT
+    ///
+    /// 00:01.000 --> 00:03.000
+    /// <v Bob>one
+    ///
+    /// 00:02.000 --> 00:04.000
+    /// <v Alice>two
+    ///
+    /// 00:03.500 --> 00:05.000
+    /// <v Bob>three
+    ///
+    /// 00:03.600 --> 00:03.900
+    /// <v Bob>four
+    /// ").unwrap();
+    /// assert_eq!(vtt.resolve_overlaps(&["Alice".to_owned(), "Bob".to_owned()]), 3);
+    /// assert_eq!(vtt.to_string(), "\
+    /// WEBVTT
+    ///
+    /// 00:01.000 --> 00:02.000
+    /// <v Bob>one
+    ///
+    /// 00:02.000 --> 00:04.000
+    /// <v Alice>two
+    ///
+    /// 00:04.000 --> 00:05.000
+    /// <v Bob>three
+    /// ");
+    /// ```
+    pub fn resolve_overlaps(&mut self, priority: &[String]) -> usize {
+        let ranks: Vec<usize> = self.cues.iter()
+            .map(|l| l.speaker.as_ref().and_then(|s| priority.iter().position(|p| p == s)).unwrap_or(priority.len()))
+            .collect();
+        let mut order: Vec<usize> = (0..self.cues.len()).collect();
+        order.sort_by_key(|&i| ranks[i]);
+        let mut dropped = vec![false; self.cues.len()];
+        let mut trimmed = 0;
+        for i in order {
+            if dropped[i] {
+                continue;
+            }
+            let winner = self.cues[i].range.clone();
+            for j in 0..self.cues.len() {
+                let range = &mut self.cues[j].range;
+                if dropped[j] || ranks[j] <= ranks[i] || range.0 >= winner.1 || range.1 <= winner.0 {
+                    continue;
+                }
+                trimmed += 1;
+                // only the times, so the cues keep their timestamp format
+                if range.0 < winner.0 {
+                    range.1.0 = winner.0.0;
+                } else if range.1 > winner.1 {
+                    range.0.0 = winner.1.0;
+                } else {
+                    dropped[j] = true;
+                }
+            }
+        }
+        let mut dropped = dropped.into_iter();
+        self.retain_cues(|_, _| !dropped.next().unwrap_or(false));
+        self.sort();
+        trimmed
+    }
+    /// Replaces cues of different speakers that overlap in time with a
+    /// single cue spanning all of them, for players that can't show two
+    /// cues at once. Each speaker's text gets its own voice span, and the
+    /// cue itself has no speaker, but the identifier and settings of the
+    /// first cue. Returns how many cues were combined.
+    ///
+    /// Expects the cues to be sorted.
+    ///
+    /// ```
+    /// # use mergevtt::WebVTT;
+    /// let mut vtt = WebVTT::from("\
+    /// WEBVTT
+    ///
+    /// c1
+    /// 00:01.000 --> 00:03.000 align:start
+    /// <v Alice>Did you
+    ///
+    /// c2
+    /// 00:02.500 --> 00:04.000
+    /// <v Bob>No!
+    ///
+    /// 00:05.000 --> 00:06.000
+    /// <v Alice>Oh.
+    /// ").unwrap();
+    /// assert_eq!(vtt.combine_overlaps(), 2);
+    /// assert_eq!(vtt.to_string(), "\
+    /// WEBVTT
+    ///
+    /// c1
+    /// 00:01.000 --> 00:04.000 align:start
+    /// <v Alice>Did you</v>
+    /// <v Bob>No!</v>
+    ///
+    /// 00:05.000 --> 00:06.000
+    /// <v Alice>Oh.
+    /// ");
+    /// ```
+    pub fn combine_overlaps(&mut self) -> usize {
+        fn combine(group: Vec<WebVTTCue>, cues: &mut Vec<WebVTTCue>) -> usize {
+            if group.iter().all(|l| l.speaker == group[0].speaker) {
+                cues.extend(group);
+                return 0;
+            }
+            let end = group.iter().map(|l| l.range.1).max().unwrap_or(group[0].range.1);
+            let text: Vec<String> = group.iter().map(|l| match &l.speaker {
+                Some(speaker) => format!("<v {}>{}</v>", escape(speaker), l.text),
+                None => l.text.clone(),
+            }).collect();
+            let mut combined = WebVTTCue::new(Timerange(group[0].range.0, end), None, text.join("\n"));
+            combined.notes = group.iter().flat_map(|l| l.notes.iter().cloned()).collect();
+            combined.identifier = group[0].identifier.clone();
+            combined.settings = group[0].settings.clone();
+            combined.source = group[0].source.clone();
+            cues.push(combined);
+            group.len()
+        }
+
+        let mut combined = 0;
+        let mut cues: Vec<WebVTTCue> = Vec::with_capacity(self.cues.len());
+        let mut group: Vec<WebVTTCue> = vec![];
+        let mut end = Timestamp::from_millis(0);
+        for cue in self.cues.drain(..) {
+            if !group.is_empty() && cue.range.0 >= end {
+                combined += combine(std::mem::take(&mut group), &mut cues);
+            }
+            end = if group.is_empty() { cue.range.1 } else { end.max(cue.range.1) };
+            group.push(cue);
+        }
+        if !group.is_empty() {
+            combined += combine(group, &mut cues);
+        }
+        self.cues = cues;
+        combined
+    }
+    /// Removes cues that are exact copies of an earlier one.
+    ///
+    /// Expects the cues to be sorted.
+    ///
+    /// ```
+    /// # use mergevtt::WebVTT;
+    /// let mut vtt = WebVTT::from("\
+    /// WEBVTT
+    ///
+    /// 00:01.000 --> 00:02.000
+    /// <v Alice>hi
+    ///
+    /// 00:01.000 --> 00:02.000
+    /// <v Bob>hi
+    ///
+    /// 00:01.000 --> 00:02.000
+    /// <v Alice>hi
+    /// ").unwrap();
+    /// vtt.sort();
+    /// vtt.dedup();
+    /// assert_eq!(vtt.to_string(), "\
+    /// WEBVTT
+    ///
+    /// 00:01.000 --> 00:02.000
+    /// <v Alice>hi
+    ///
+    /// 00:01.000 --> 00:02.000
+    /// <v Bob>hi
+    /// ");
+    /// ```
+    pub fn dedup(&mut self) {
+        self.retain_cues(|kept, cue| {
+            // copies start at the same time, so only those need checking
+            !kept.iter().rev()
+                .take_while(|c| c.range.0 == cue.range.0)
+                .any(|c| c == cue)
+        });
+    }
+    /// Keeps the cues `keep` says so, given the cues kept so far. The
+    /// notes of dropped cues go to the next cue that is kept, or to the
+    /// notes of the file after the last one.
+    fn retain_cues(&mut self, mut keep: impl FnMut(&[WebVTTCue], &WebVTTCue) -> bool) {
+        let mut cues: Vec<WebVTTCue> = Vec::with_capacity(self.cues.len());
+        let mut notes: Vec<String> = vec![];
+        for mut cue in self.cues.drain(..) {
+            if keep(&cues, &cue) {
+                notes.append(&mut cue.notes);
+                cue.notes = std::mem::take(&mut notes);
+                cues.push(cue);
+            } else {
+                notes.append(&mut cue.notes);
+            }
+        }
+        self.notes.append(&mut notes);
+        self.cues = cues;
+    }
+    /// Keeps only cues that overlap the window from `start` to `end`.
+    ///
+    /// Cues that stick out of the window are kept whole, unless `trim` is
+    /// set, then they are cut to the window.
+    pub fn retain_window(&mut self, start: Timestamp, end: Timestamp, trim: bool) {
+        self.retain_cues(|_, l| l.range.0 < end && l.range.1 > start);
+        if trim {
+            for l in self.cues.iter_mut() {
+                // only the time, so the cue keeps its own timestamp format
+                l.range.0.0 = l.range.0.0.max(start.0);
+                l.range.1.0 = l.range.1.0.min(end.0);
+            }
+        }
+    }
+    /// The distinct speakers of all cues, sorted.
+    pub fn speakers(&self) -> Vec<&str> {
+        let mut speakers: Vec<&str> = self.cues.iter().filter_map(|l| l.speaker.as_deref()).collect();
+        speakers.sort_unstable();
+        speakers.dedup();
+        speakers
+    }
+    /// Adds a `STYLE` block giving every speaker a color, taken in turn from
+    /// a palette that reads well on the usual black caption background.
+    ///
+    /// ```
+    /// # use mergevtt::WebVTT;
+    /// let mut vtt = WebVTT::from("WEBVTT\n\n00:01.000 --> 00:02.000\n<v Bob>hi\n\n00:02.000 --> 00:03.000\n<v Alice \"A\">hey\n").unwrap();
+    /// vtt.colorize_speakers();
+    /// assert!(vtt.to_string().starts_with("\
+    /// WEBVTT
+    ///
+    /// STYLE
+    /// ::cue(v[voice=\"Alice \\\"A\\\"\"]) { color: yellow; }
+    /// ::cue(v[voice=\"Bob\"]) { color: cyan; }
+    ///
+    /// 00:01.000 --> 00:02.000
+    /// "));
+    /// ```
+    pub fn colorize_speakers(&mut self) {
+        const PALETTE: [&str; 6] = ["yellow", "cyan", "lime", "magenta", "orange", "lightblue"];
+        if self.speakers().is_empty() {
+            return;
+        }
+        let mut style = String::from("STYLE");
+        for (speaker, color) in self.speakers().into_iter().zip(PALETTE.iter().cycle()) {
+            // a CSS string, so only quotes and backslashes need escaping
+            let speaker = speaker.replace('\\', "\\\\").replace('"', "\\\"");
+            style += &format!("\n::cue(v[voice=\"{speaker}\"]) {{ color: {color}; }}");
+        }
+        self.styles.push(style);
+    }
+    /// Gives every speaker a `REGION` of their own, stacked from the bottom
+    /// up, and moves their cues into it. With one speaker per input file
+    /// this keeps the files apart on screen.
+    ///
+    /// ```
+    /// # use mergevtt::WebVTT;
+    /// let mut alice = WebVTT::from("WEBVTT\n\n00:01.000 --> 00:02.000 align:start\nhi\n").unwrap();
+    /// let mut bob = WebVTT::from("WEBVTT\n\n00:01.500 --> 00:03.000\nhey\n").unwrap();
+    /// alice.set_speaker_for_all_lines("Alice Smith");
+    /// bob.set_speaker_for_all_lines("Bob");
+    /// alice.merge_with(bob);
+    /// alice.assign_regions();
+    /// assert_eq!(alice.to_string(), "\
+    /// WEBVTT
+    ///
+    /// REGION
+    /// id:Alice_Smith
+    /// width:80%
+    /// lines:3
+    /// regionanchor:0%,100%
+    /// viewportanchor:10%,90%
+    ///
+    /// REGION
+    /// id:Bob
+    /// width:80%
+    /// lines:3
+    /// regionanchor:0%,100%
+    /// viewportanchor:10%,74%
+    ///
+    /// 00:01.000 --> 00:02.000 align:start region:Alice_Smith
+    /// <v Alice Smith>hi
+    ///
+    /// 00:01.500 --> 00:03.000 region:Bob
+    /// <v Bob>hey
+    /// ");
+    /// ```
+    pub fn assign_regions(&mut self) {
+        let mut ids: Vec<(String, String)> = vec![];
+        for (i, speaker) in self.speakers().into_iter().enumerate() {
+            // ids can't contain whitespace or arrows
+            let mut id: String = speaker.chars().map(|c| if c.is_alphanumeric() { c } else { '_' }).collect();
+            if ids.iter().any(|(_, other)| *other == id) {
+                id += &format!("_{}", i + 1);
+            }
+            ids.push((speaker.to_owned(), id));
+        }
+        for (i, (_, id)) in ids.iter().enumerate() {
+            let y = 90usize.saturating_sub(16 * i).max(10);
+            self.regions.push(format!(
+                "REGION\nid:{id}\nwidth:80%\nlines:3\nregionanchor:0%,100%\nviewportanchor:10%,{y}%"
+            ));
+        }
+        for l in self.cues.iter_mut() {
+            let Some((_, id)) = ids.iter().find(|(speaker, _)| Some(speaker) == l.speaker.as_ref()) else {
+                continue;
+            };
+            let mut settings: Vec<String> = l.settings.as_deref().unwrap_or("")
+                .split_whitespace()
+                .filter(|s| !s.starts_with("region:"))
+                .map(str::to_owned)
+                .collect();
+            settings.push(format!("region:{id}"));
+            l.settings = Some(settings.join(" "));
+        }
+    }
+    /// Keeps only cues of the given speakers, dropping cues without one.
+    pub fn retain_speakers(&mut self, speakers: &[String]) {
+        self.retain_cues(|_, l| l.speaker.as_ref().is_some_and(|s| speakers.contains(s)));
+    }
+    /// Trims whitespace from both ends of every line of cue text. Lines
+    /// left empty are dropped, since they would end the cue.
+    ///
+    /// ```
+    /// # use mergevtt::WebVTT;
+    /// let mut vtt = WebVTT::from("WEBVTT\n\n00:01.000 --> 00:02.000\n  one  two \n\tthree\n \n").unwrap();
+    /// vtt.trim_text();
+    /// assert_eq!(vtt.to_string(), "WEBVTT\n\n00:01.000 --> 00:02.000\none  two\nthree\n");
+    /// ```
+    pub fn trim_text(&mut self) {
+        for cue in self.cues.iter_mut() {
+            let lines: Vec<&str> = cue.text.lines().map(str::trim).filter(|l| !l.is_empty()).collect();
+            cue.text = lines.join("\n");
+        }
+    }
+    /// Like [`WebVTT::trim_text`], but also turns every run of whitespace
+    /// inside a line into a single space. Line breaks stay.
+    ///
+    /// ```
+    /// # use mergevtt::WebVTT;
+    /// let mut vtt = WebVTT::from("WEBVTT\n\n00:01.000 --> 00:02.000\nhello   world \n second\t row\n").unwrap();
+    /// vtt.normalize_whitespace();
+    /// assert_eq!(vtt.cues().next().unwrap().text(), "hello world\nsecond row");
+    /// ```
+    pub fn normalize_whitespace(&mut self) {
+        for cue in self.cues.iter_mut() {
+            let lines: Vec<String> = cue.text.lines()
+                .map(|l| l.split_whitespace().collect::<Vec<_>>().join(" "))
+                .filter(|l| !l.is_empty())
+                .collect();
+            cue.text = lines.join("\n");
+        }
+    }
+    /// Breaks lines of cue text longer than `max` characters at spaces.
+    /// Words longer than that get a line of their own, and markup counts
+    /// like any other text.
+    ///
+    /// ```
+    /// # use mergevtt::WebVTT;
+    /// let mut vtt = WebVTT::from("WEBVTT\n\n00:01.000 --> 00:02.000\nÜber den Wolken muss die Freiheit wohl grenzenlos sein\n").unwrap();
+    /// vtt.wrap_text(32);
+    /// assert_eq!(vtt.cues().next().unwrap().text(), "Über den Wolken muss die\nFreiheit wohl grenzenlos sein");
+    /// ```
+    ///
+    /// Lines of only spaces are left as they are:
+    ///
+    /// ```
+    /// # use mergevtt::WebVTT;
+    /// let mut vtt = WebVTT::from("WEBVTT\n\n00:01.000 --> 00:02.000\na\n        \nb\n").unwrap();
+    /// vtt.wrap_text(3);
+    /// assert_eq!(vtt.cues().next().unwrap().text(), "a\n        \nb");
+    /// assert_eq!(WebVTT::from(&vtt.to_string()).unwrap(), vtt);
+    /// ```
+    pub fn wrap_text(&mut self, max: usize) {
+        for cue in self.cues.iter_mut() {
+            let mut wrapped = String::with_capacity(cue.text.len());
+            for (i, line) in cue.text.lines().enumerate() {
+                if i > 0 {
+                    wrapped.push('\n');
+                }
+                // a line of only spaces has no words, and dropping them all
+                // would leave an empty line that ends the cue
+                if line.chars().count() <= max || line.trim_matches(' ').is_empty() {
+                    wrapped.push_str(line);
+                    continue;
+                }
+                let mut width = 0;
+                for word in line.split(' ').filter(|w| !w.is_empty()) {
+                    let len = word.chars().count();
+                    if width > 0 && width + 1 + len > max {
+                        wrapped.push('\n');
+                        width = 0;
+                    } else if width > 0 {
+                        wrapped.push(' ');
+                        width += 1;
+                    }
+                    wrapped.push_str(word);
+                    width += len;
+                }
+            }
+            cue.text = wrapped;
+        }
+    }
+    /// Drops cues shorter than `min` and returns how many were dropped.
+    ///
+    /// ```
+    /// # use mergevtt::WebVTT;
+    /// # use std::time::Duration;
+    /// let mut vtt = WebVTT::from("WEBVTT\n\n00:01.000 --> 00:01.020\nah\n\n00:02.000 --> 00:02.019\num\n").unwrap();
+    /// assert_eq!(vtt.retain_min_duration(Duration::from_millis(20)), 1);
+    /// assert_eq!(vtt.cues().next().unwrap().text(), "ah");
+    /// ```
+    pub fn retain_min_duration(&mut self, min: Duration) -> usize {
+        let before = self.cues.len();
+        self.retain_cues(|_, l| l.range.duration() >= min);
+        before - self.cues.len()
+    }
+    /// Drops the first `skip` cues and keeps at most `limit` of the rest.
+    pub fn paginate(&mut self, skip: usize, limit: Option<usize>) {
+        let mut i = 0;
+        self.retain_cues(|_, _| {
+            i += 1;
+            i > skip && limit.is_none_or(|limit| i - skip <= limit)
+        });
+    }
+    /// Replaces all cue identifiers with their position, starting at 1.
+    pub fn renumber(&mut self) {
+        for (i, l) in self.cues.iter_mut().enumerate() {
+            l.identifier = Some((i + 1).to_string());
+        }
+    }
+    /// Checks that every cue starts before it ends.
+    pub fn validate(&self) -> Result<(), WebVTTError> {
+        for l in self.cues.iter() {
+            l.range.validate()?;
+        }
+        Ok(())
+    }
+    /// The stretches longer than `min` in which no cue is shown.
+    ///
+    /// Expects the cues to be sorted.
+    pub fn gaps(&self, min: Duration) -> Vec<Timerange> {
+        let mut gaps = vec![];
+        let mut cues = self.cues.iter();
+        let Some(first) = cues.next() else {
+            return gaps;
+        };
+        let mut end = first.range.1;
+        for l in cues {
+            if l.range.0 - end > min {
+                gaps.push(Timerange(end, l.range.0));
+            }
+            end = end.max(l.range.1);
+        }
+        gaps
+    }
+    /// All pairs of cues from different speakers that overlap in time.
+    ///
+    /// Expects the cues to be sorted.
+    pub fn overlaps(&self) -> Vec<(&WebVTTCue, &WebVTTCue)> {
+        let mut overlaps = vec![];
+        for (i, a) in self.cues.iter().enumerate() {
+            for b in self.cues[i + 1..].iter().take_while(|b| b.range.0 < a.range.1) {
+                if a.speaker != b.speaker {
+                    overlaps.push((a, b));
+                }
+            }
+        }
+        overlaps
+    }
+    /// Applies the `X-TIMESTAMP-MAP` header of an HLS segment, if there is
+    /// one, and removes it. The cues move by the difference between
+    /// `MPEGTS`, in 90kHz ticks, and `LOCAL`. Returns whether there was a
+    /// map to apply.
+    ///
+    /// Without this the header is kept as is.
+    ///
+    /// ```
+    /// # use mergevtt::WebVTT;
+    /// let mut segment = WebVTT::from("\
+    /// WEBVTT
+    /// X-TIMESTAMP-MAP=MPEGTS:900000,LOCAL:00:00:00.000
+    ///
+    /// 00:00.500 --> 00:02.000
+    /// hi
+    /// ").unwrap();
+    /// assert!(segment.to_string().contains("X-TIMESTAMP-MAP"));
+    /// assert!(segment.apply_timestamp_map().unwrap());
+    /// assert_eq!(segment.to_string(), "WEBVTT\n\n00:10.500 --> 00:12.000\nhi\n");
+    /// ```
+    pub fn apply_timestamp_map(&mut self) -> Result<bool, WebVTTError> {
+        let Some(map) = self.header.lines().find_map(|l| l.strip_prefix("X-TIMESTAMP-MAP=")) else {
+            return Ok(false);
+        };
+        let invalid = || WebVTTError::Parsing("MPEGTS:<ticks>,LOCAL:<timestamp>".to_owned(), map.to_owned());
+        let (mut mpegts, mut local) = (None, None);
+        for field in map.split(',') {
+            match field.trim().split_once(':') {
+                Some(("MPEGTS", ticks)) => mpegts = Some(ticks.parse::<u64>().map_err(|_| invalid())?),
+                Some(("LOCAL", time)) => local = Some(Timestamp::from(time)?),
+                _ => return Err(invalid()),
+            }
+        }
+        let (Some(mpegts), Some(local)) = (mpegts, local) else {
+            return Err(invalid());
+        };
+        let media = Duration::from_millis(mpegts.saturating_add(45) / 90);
+        match media.checked_sub(local.0) {
+            Some(delta) => self.shift(delta, true),
+            None => self.shift(local.0 - media, false),
+        };
+        self.header = self.header.lines()
+            .filter(|l| !l.starts_with("X-TIMESTAMP-MAP="))
+            .collect::<Vec<_>>()
+            .join("\n");
+        Ok(true)
+    }
+    /// Checks the markup of every cue, see [`WebVTTCue::tag_problems`].
+    pub fn tag_problems(&self) -> Vec<(&WebVTTCue, String)> {
+        self.cues.iter()
+            .flat_map(|l| l.tag_problems().into_iter().map(move |problem| (l, problem)))
+            .collect()
+    }
+    /// Moves every cue `delta` later, or earlier if `forward` is false.
+    ///
+    /// Shifting earlier clamps at zero. Returns how many cues were clamped.
+    ///
+    /// ```
+    /// # use std::time::Duration;
+    /// # use mergevtt::WebVTT;
+    /// let mut vtt = WebVTT::from("WEBVTT\n\n00:01.000 --> 00:02.000\none\n\n00:03.000 --> 00:04.000\ntwo\n").unwrap();
+    /// assert_eq!(vtt.shift(Duration::from_secs(10), true), 0);
+    /// assert_eq!(vtt.to_string(), "WEBVTT\n\n00:11.000 --> 00:12.000\none\n\n00:13.000 --> 00:14.000\ntwo\n");
+    /// ```
+    pub fn shift(&mut self, delta: Duration, forward: bool) -> usize {
+        self.cues.iter_mut()
+            .map(|l| l.range.shift(delta, forward))
+            .filter(|&clamped| clamped)
+            .count()
+    }
+    /// Multiplies all timestamps by `factor`, rounded to milliseconds.
+    pub fn scale(&mut self, factor: f64) -> Result<(), WebVTTError> {
+        if !(factor.is_finite() && factor > 0.0) {
+            return Err(WebVTTError::InvalidScale(factor));
+        }
+        for l in self.cues.iter_mut() {
+            l.range.0.scale(factor);
+            l.range.1.scale(factor);
+        }
+        Ok(())
+    }
+    /// Adds the cues of `other` in order.
+    ///
+    /// Sorted files are interleaved in a single pass, unsorted ones get
+    /// sorted first. On ties the cues of `self` come first.
+    ///
+    /// Notes before a cue stay before it.
+    ///
+    /// ```
+    /// # use mergevtt::WebVTT;
+    /// let mut a = WebVTT::from("WEBVTT\n\nNOTE a\n\n00:01.000 --> 00:02.000\n<v Alice>one\n").unwrap();
+    /// let b = WebVTT::from("WEBVTT\n\nNOTE b\n\n00:00.500 --> 00:03.000\n<v Bob>two\n").unwrap();
+    /// a.merge_with(b);
+    /// assert_eq!(a.to_string(), "\
+    /// WEBVTT
+    ///
+    /// NOTE b
+    ///
+    /// 00:00.500 --> 00:03.000
+    /// <v Bob>two
+    ///
+    /// NOTE a
+    ///
+    /// 00:01.000 --> 00:02.000
+    /// <v Alice>one
+    /// ");
+    ///
+    /// let mut unsorted = WebVTT::from("WEBVTT\n\n00:05.000 --> 00:06.000\nlate\n\n00:01.000 --> 00:02.000\nearly\n").unwrap();
+    /// unsorted.merge_with(WebVTT::from("WEBVTT\n\n00:03.000 --> 00:04.000\nmiddle\n").unwrap());
+    /// assert_eq!(unsorted.cues().map(|c| c.text()).collect::<Vec<_>>(), ["early", "middle", "late"]);
+    /// ```
+    pub fn merge_with(&mut self, mut other: WebVTT) {
+        for file in [&mut *self, &mut other] {
+            if !file.is_sorted() {
+                file.sort();
+            }
+        }
+        if self.header.is_empty() {
+            self.header = other.header;
+        }
+        for style in other.styles {
+            if !self.styles.contains(&style) {
+                self.styles.push(style);
+            }
+        }
+        for region in other.regions {
+            if !self.regions.contains(&region) {
+                self.regions.push(region);
+            }
+        }
+        self.notes.extend(other.notes);
+        self.cues = merge_sorted(std::mem::take(&mut self.cues), other.cues);
+    }
+    /// Merges many files at once, with the same result as merging them one
+    /// after another with [`WebVTT::merge_with`]. Unsorted files get sorted
+    /// first.
+    ///
+    /// ```
+    /// # use mergevtt::WebVTT;
+    /// let files: Vec<_> = [
+    ///     "WEBVTT\n\n00:01.000 --> 00:04.000\n<v A>a1\n\n00:05.000 --> 00:06.000\n<v A>a2\n",
+    ///     "WEBVTT\n\n00:02.000 --> 00:05.500\n<v B>b1\n\n00:05.000 --> 00:06.000\n<v A>b2\n",
+    ///     "WEBVTT\n\n00:00.500 --> 00:03.000\n<v C>c1\n\n00:05.000 --> 00:06.000\n<v C>c2\n",
+    /// ].iter().map(|s| WebVTT::from(s).unwrap()).collect();
+    ///
+    /// let mut pairwise = WebVTT::new();
+    /// for file in files.clone() {
+    ///     pairwise.merge_with(file);
+    /// }
+    /// let merged = WebVTT::merge_all(files);
+    /// assert_eq!(merged, pairwise);
+    /// assert_eq!(merged.cues().map(|c| c.text()).collect::<Vec<_>>(), ["c1", "a1", "b1", "a2", "b2", "c2"]);
+    /// ```
+    pub fn merge_all(files: Vec<WebVTT>) -> WebVTT {
+        /// The next cue of the file at the index, ordered so the heap pops
+        /// the earliest one, and on ties the one from the earlier file.
+        struct Head(WebVTTCue, usize);
+        impl PartialEq for Head {
+            fn eq(&self, other: &Self) -> bool {
+                self.cmp(other).is_eq()
+            }
+        }
+        impl Eq for Head {}
+        impl PartialOrd for Head {
+            fn partial_cmp(&self, other: &Self) -> Option<std::cmp::Ordering> {
+                Some(self.cmp(other))
+            }
+        }
+        impl Ord for Head {
+            fn cmp(&self, other: &Self) -> std::cmp::Ordering {
+                other.0.cmp(&self.0).then(other.1.cmp(&self.1))
+            }
+        }
+
+        let mut merged = WebVTT::new();
+        let mut files: Vec<_> = files.into_iter().map(|mut file| {
+            if !file.is_sorted() {
+                file.sort();
+            }
+            let cues = std::mem::take(&mut file.cues);
+            merged.merge_with(file);
+            cues.into_iter()
+        }).collect();
+
+        let mut heap: std::collections::BinaryHeap<Head> = files.iter_mut()
+            .enumerate()
+            .filter_map(|(i, cues)| cues.next().map(|cue| Head(cue, i)))
+            .collect();
+        merged.cues.reserve(files.iter().map(|cues| cues.len()).sum::<usize>() + heap.len());
+        while let Some(Head(cue, i)) = heap.pop() {
+            merged.cues.push(cue);
+            if let Some(next) = files[i].next() {
+                heap.push(Head(next, i));
+            }
+        }
+        merged
+    }
+    /// Stitches HLS segments into one file: applies the
+    /// `X-TIMESTAMP-MAP` of every segment, merges them, and drops the cues
+    /// repeated at the boundaries.
+    pub fn concat_segments(mut segments: Vec<WebVTT>) -> Result<WebVTT, WebVTTError> {
+        for segment in segments.iter_mut() {
+            segment.apply_timestamp_map()?;
+            segment.sort();
+        }
+        let mut merged = Self::merge_all(segments);
+        merged.dedup();
+        Ok(merged)
+    }
+    /// The whole file as text, the same as `to_string()`.
+    pub fn render(&self) -> String {
+        self.to_string()
+    }
+    /// Writes the same as [`Display`], but piece by piece instead of
+    /// building one big string.
+    pub fn write_to<W: std::io::Write>(&self, w: &mut W) -> std::io::Result<()> {
+        write!(w, "{self}")
+    }
+    /// Everything after the header in output order
+    fn blocks(&self) -> impl Iterator<Item = Block<'_>> {
+        self.regions.iter()
+            .chain(self.styles.iter())
+            .chain(self.notes.iter())
+            .map(|b| Block::Text(b))
+            .chain(self.cues.iter().flat_map(|cue| {
+                cue.notes.iter()
+                    .map(|note| Block::Text(note))
+                    .chain(cue.source.as_deref().filter(|_| self.render_options.annotate_source).map(Block::Source))
+                    .chain(std::iter::once(Block::Cue(cue, &self.render_options)))
+            }))
+    }
+    /// Renders the cues as SubRip, with speakers as `Speaker: ` prefixes.
+    ///
+    /// Notes, styles, regions, identifiers, and cue settings are dropped,
+    /// and so are all tags but `<b>`, `<i>`, and `<u>`, which SubRip has
+    /// too. Voice tags in the text turn into prefixes as well, and escaped
+    /// characters like `&amp;` are written out.
+    ///
+    /// ```
+    /// # use mergevtt::WebVTT;
+    /// let vtt = WebVTT::from("\
+    /// WEBVTT
+    ///
+    /// 00:01.000 --> 00:02.500
+    /// <v Alice>Hi Bob
+    ///
+    /// 00:03.000 --> 00:04.000
+    /// Hi
+    /// <c.name>Alice</c>
+    ///
+    /// 00:05.000 --> 00:06.000
+    /// <v.loud Bob>It's <i.shout>you</i> &amp; <00:05.500><b>me</b></v>
+    /// ").unwrap();
+    /// assert_eq!(vtt.to_srt(), "\
+    /// 1
+    /// 00:00:01,000 --> 00:00:02,500
+    /// Alice: Hi Bob
+    ///
+    /// 2
+    /// 00:00:03,000 --> 00:00:04,000
+    /// Hi
+    /// Alice
+    ///
+    /// 3
+    /// 00:00:05,000 --> 00:00:06,000
+    /// Bob: It's <i>you</i> & <b>me</b>
+    /// ");
+    /// ```
+    pub fn to_srt(&self) -> String {
+        let mut srt = String::new();
+        for (i, l) in self.cues.iter().enumerate() {
+            if i > 0 {
+                srt.push('\n');
+            }
+            srt += &format!("{}\n{} --> {}\n", i + 1, l.range.0.to_srt(), l.range.1.to_srt());
+            if let Some(speaker) = &l.speaker {
+                srt += &format!("{speaker}: ");
+            }
+            srt += &strip_tags_except(&l.text, &["b", "i", "u", "v"]);
+            srt.push('\n');
+        }
+        srt
+    }
+    /// Renders the cues as a plain text transcript. Neighbouring cues of the
+    /// same speaker become one paragraph, starting with `[HH:MM:SS]` if
+    /// `timestamps` is set. Markup is removed.
+    ///
+    /// ```
+    /// # use mergevtt::WebVTT;
+    /// let vtt = WebVTT::from("\
+    /// WEBVTT
+    ///
+    /// 00:00:01.000 --> 00:00:02.000
+    /// <v Alice>Hi <i>Bob</i>,
+    ///
+    /// 00:00:02.000 --> 00:00:03.000
+    /// <v Alice>how are
+    /// you?
+    ///
+    /// 01:00:03.000 --> 01:00:04.000
+    /// <v Bob>Fine &amp; you?
+    /// ").unwrap();
+    /// assert_eq!(vtt.to_transcript(true), "[00:00:01] Alice: Hi Bob, how are you?\n\n[01:00:03] Bob: Fine & you?\n");
+    /// assert_eq!(vtt.to_transcript(false), "Alice: Hi Bob, how are you?\n\nBob: Fine & you?\n");
+    /// ```
+    pub fn to_transcript(&self, timestamps: bool) -> String {
+        let mut transcript = String::new();
+        let mut speaker: Option<Option<&str>> = None;
+        for l in self.cues.iter() {
+            if speaker == Some(l.speaker.as_deref()) {
+                transcript.push(' ');
+            } else {
+                if speaker.is_some() {
+                    transcript += "\n\n";
+                }
+                if timestamps {
+                    let secs = l.range.0.0.as_secs();
+                    transcript += &format!("[{:02}:{:02}:{:02}] ", secs / (60 * 60), secs / 60 % 60, secs % 60);
+                }
+                if let Some(name) = &l.speaker {
+                    transcript += &format!("{name}: ");
+                }
+                speaker = Some(l.speaker.as_deref());
+            }
+            transcript += &strip_tags(&l.text).split_whitespace().collect::<Vec<_>>().join(" ");
+        }
+        if speaker.is_some() {
+            transcript.push('\n');
+        }
+        transcript
+    }
+}
+impl Display for WebVTT {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        writeln!(f, "WEBVTT{}", self.header)?;
+        for block in self.blocks() {
+            write!(f, "\n{block}\n")?;
+        }
+        Ok(())
+    }
+}
+
+/// Interleaves two sorted lists, preferring `a` on ties
+fn merge_sorted(a: Vec<WebVTTCue>, b: Vec<WebVTTCue>) -> Vec<WebVTTCue> {
+    let mut merged = Vec::with_capacity(a.len() + b.len());
+    let mut a = a.into_iter().peekable();
+    let mut b = b.into_iter().peekable();
+    loop {
+        let next = match (a.peek(), b.peek()) {
+            (Some(x), Some(y)) if y < x => b.next(),
+            (Some(_), _) => a.next(),
+            (None, _) => b.next(),
+        };
+        match next {
+            Some(cue) => merged.push(cue),
+            None => return merged,
+        }
+    }
+}
+
+/// Strips a byte order mark and turns all line endings into LF
+fn normalize(string: &str) -> String {
+    let string = string.strip_prefix('\u{feff}').unwrap_or(string);
+    string.replace("\r\n", "\n").replace('\r', "\n")
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum BlockKind {
+    Note,
+    Style,
+    Region,
+}
+impl BlockKind {
+    fn from(line: &str) -> Option<Self> {
+        let keyword = line.split([' ', '\t']).next().unwrap_or("");
+        match keyword {
+            // like in the spec, an arrow makes it a cue, even `NOTE -->`
+            _ if line.contains("-->") => None,
+            "NOTE" => Some(Self::Note),
+            "STYLE" if keyword.len() == line.trim_end().len() => Some(Self::Style),
+            "REGION" if keyword.len() == line.trim_end().len() => Some(Self::Region),
+            _ => None,
+        }
+    }
+}
+
+/// Escapes text for use inside a cue, like in a voice tag
+fn escape(text: &str) -> String {
+    text.replace('&', "&amp;").replace('<', "&lt;").replace('>', "&gt;")
+}
+
+/// Removes all `<...>` tags and unescapes what's left
+fn strip_tags(text: &str) -> String {
+    strip_tags_except(text, &[])
+}
+
+/// Like [`strip_tags`], but keeps the tags named in `keep`, without their
+/// classes. A kept `v` becomes a `Speaker: ` prefix.
+fn strip_tags_except(text: &str, keep: &[&str]) -> String {
+    let mut stripped = String::with_capacity(text.len());
+    let mut tag: Option<String> = None;
+    for c in text.chars() {
+        match (c, &mut tag) {
+            ('<', _) => tag = Some(String::new()),
+            ('>', Some(content)) => {
+                let (slash, name) = match content.strip_prefix('/') {
+                    Some(name) => ("/", name),
+                    None => ("", content.as_str()),
+                };
+                let (name, annotation) = name.split_once([' ', '\t']).unwrap_or((name, ""));
+                let name = name.split('.').next().unwrap_or("");
+                match name {
+                    _ if !keep.contains(&name) => {},
+                    "v" if slash.is_empty() => stripped += &format!("{}: ", annotation.trim()),
+                    "v" => {},
+                    _ => stripped += &format!("<{slash}{name}>"),
+                }
+                tag = None;
+            },
+            (c, Some(content)) => content.push(c),
+            (c, None) => stripped.push(c),
+        }
+    }
+    unescape(&stripped)
+}
+
+fn unescape(text: &str) -> String {
+    text.replace("&lt;", "<").replace("&gt;", ">").replace("&amp;", "&")
+}
+
+/// Splits a timing line into the timerange and the cue settings after it.
+fn split_cue_settings(line: &str) -> (&str, Option<&str>) {
+    let Some(arrow) = line.find("-->") else {
+        return (line, None);
+    };
+    let after_arrow = arrow + "-->".len();
+    let rest = &line[after_arrow..];
+    let end_start = rest.len() - rest.trim_start().len();
+    let end_len = rest[end_start..].find(char::is_whitespace).unwrap_or(rest.len() - end_start);
+    let (timing, settings) = line.split_at(after_arrow + end_start + end_len);
+    match settings.trim() {
+        "" => (timing, None),
+        settings => (timing, Some(settings)),
+    }
+}
+
+#[derive(Debug, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct WebVTTCue {
+    /// `NOTE` blocks right before the cue, they stay with it when sorting
+    /// and merging
+    #[cfg_attr(feature = "serde", serde(default))]
+    notes: Vec<String>,
+    #[cfg_attr(feature = "serde", serde(default))]
+    identifier: Option<String>,
+    #[cfg_attr(feature = "serde", serde(flatten))]
+    range: Timerange,
+    #[cfg_attr(feature = "serde", serde(default))]
+    settings: Option<String>,
+    #[cfg_attr(feature = "serde", serde(default))]
+    speaker: Option<String>,
+    text: String,
+    /// The timing exactly as it was parsed, printed instead of `range` with
+    /// [`RenderOptions::keep_timing`] for as long as the two still agree
+    #[cfg_attr(feature = "serde", serde(skip))]
+    raw_timing: Option<(Timerange, String)>,
+    /// Where the cue was loaded from
+    #[cfg_attr(feature = "serde", serde(skip))]
+    source: Option<String>,
+}
+impl WebVTTCue {
+    /// The speaker is plain text, it gets escaped when printed in the voice
+    /// tag. The text is printed as is, so it can contain markup.
+    ///
+    /// ```
+    /// # use mergevtt::{Timerange, Timestamp, WebVTTCue};
+    /// let range = Timerange::new(Timestamp::from_millis(0), Timestamp::from_millis(1000));
+    /// let cue = WebVTTCue::new(range, Some("A<B & C>".to_owned()), "<i>hi</i>".to_owned());
+    /// assert_eq!(cue.to_string(), "00:00:00.000 --> 00:00:01.000\n<v A&lt;B &amp; C&gt;><i>hi</i>");
+    /// ```
+    pub fn new(range: Timerange, speaker: Option<String>, text: String) -> Self {
+        Self { notes: vec![], identifier: None, range, settings: None, speaker, text, raw_timing: None, source: None }
+    }
+    pub fn from(identifier: Option<String>, range: &Timerange, string: &str) -> Result<Self, WebVTTError> {
+        // a leading `<v Speaker>` tag becomes the speaker, voice tags with
+        // classes stay part of the text
+        let (speaker, text) = match string.strip_prefix("<v ").and_then(|s| s.split_once('>')) {
+            Some((speaker, text)) => (Some(unescape(speaker.trim())), text),
+            None => (None, string),
+        };
+        Ok(Self {
+            notes: vec![],
+            identifier,
+            range: range.to_owned(),
+            settings: None,
+            speaker,
+            text: text.to_owned(),
+            raw_timing: None,
+            source: None,
+        })
+    }
+    /// Describes unknown, unclosed, and stray closing tags in the text.
+    /// Timestamp tags need no closing tag, and neither does `<v>`.
+    ///
+    /// ```
+    /// # use mergevtt::{Timerange, Timestamp, WebVTTCue};
+    /// let range = Timerange::new(Timestamp::from_millis(0), Timestamp::from_millis(1000));
+    /// let cue = |text: &str| WebVTTCue::new(range.clone(), None, text.to_owned());
+    /// assert!(cue("<b>a <c.loud>b</c></b> <00:00.500>c <v Bob>d").tag_problems().is_empty());
+    /// assert_eq!(cue("<b><i>a</b>").tag_problems(), ["unclosed <i>"]);
+    /// assert_eq!(cue("a</u> <blink>b").tag_problems(), ["unexpected </u>", "unknown tag <blink>"]);
+    /// assert_eq!(cue("<ruby>a<rt>b").tag_problems(), ["unclosed <rt>", "unclosed <ruby>"]);
+    /// assert_eq!(cue("a <b").tag_problems(), ["unterminated tag"]);
+    /// ```
+    pub fn tag_problems(&self) -> Vec<String> {
+        const TAGS: [&str; 8] = ["c", "i", "b", "u", "ruby", "rt", "v", "lang"];
+        let mut problems = vec![];
+        let mut open: Vec<&str> = vec![];
+        let mut rest = self.text.as_str();
+        while let Some(start) = rest.find('<') {
+            let Some(len) = rest[start..].find('>') else {
+                problems.push("unterminated tag".to_owned());
+                break;
+            };
+            let tag = &rest[start + 1..start + len];
+            rest = &rest[start + len + 1..];
+            if tag.starts_with(|c: char| c.is_ascii_digit()) {
+                continue;
+            }
+            if let Some(name) = tag.strip_prefix('/') {
+                match open.iter().rposition(|&o| o == name) {
+                    Some(i) => {
+                        for unclosed in open.drain(i..).skip(1).rev().filter(|&o| o != "v") {
+                            problems.push(format!("unclosed <{unclosed}>"));
+                        }
+                    },
+                    None => problems.push(format!("unexpected </{name}>")),
+                }
+                continue;
+            }
+            let name = tag.split(['.', ' ', '\t']).next().unwrap_or("");
+            match TAGS.contains(&name) {
+                true => open.push(name),
+                false => problems.push(format!("unknown tag <{name}>")),
+            }
+        }
+        for unclosed in open.into_iter().rev().filter(|&o| o != "v") {
+            problems.push(format!("unclosed <{unclosed}>"));
+        }
+        problems
+    }
+    /// The `NOTE` blocks right before this cue. [`WebVTT`] methods that
+    /// drop cues move the notes of dropped cues on to the next cue that is
+    /// kept, or to the notes of the file if none is.
+    pub fn notes(&self) -> &[String] {
+        &self.notes
+    }
+    /// Where the cue came from, if known. Doesn't count for comparisons.
+    pub fn source(&self) -> Option<&str> {
+        self.source.as_deref()
+    }
+    pub fn identifier(&self) -> Option<&str> {
+        self.identifier.as_deref()
+    }
+    pub fn timerange(&self) -> &Timerange {
+        &self.range
+    }
+    pub fn settings(&self) -> Option<&str> {
+        self.settings.as_deref()
+    }
+    pub fn speaker(&self) -> Option<&str> {
+        self.speaker.as_deref()
+    }
+    pub fn set_speaker(&mut self, speaker: Option<String>) {
+        self.speaker = speaker;
+    }
+    pub fn text(&self) -> &str {
+        &self.text
+    }
+    pub fn set_text(&mut self, text: String) {
+        self.text = text;
+    }
+    fn push_line(&mut self, line: &str) {
+        self.text.push('\n');
+        self.text.push_str(line);
+    }
+}
+// how the timing was written doesn't matter, just like for `Timestamp`
+impl PartialEq for WebVTTCue {
+    fn eq(&self, other: &Self) -> bool {
+        self.notes == other.notes
+            && self.identifier == other.identifier
+            && self.range == other.range
+            && self.settings == other.settings
+            && self.speaker == other.speaker
+            && self.text == other.text
+    }
+}
+impl Eq for WebVTTCue {}
+/// By time range, then speaker, then text. Identifier, settings, and notes only
+/// break ties so that the order agrees with `==`.
+impl PartialOrd for WebVTTCue {
+    fn partial_cmp(&self, other: &Self) -> Option<std::cmp::Ordering> {
+        Some(self.cmp(other))
+    }
+}
+impl Ord for WebVTTCue {
+    fn cmp(&self, other: &Self) -> std::cmp::Ordering {
+        self.range.cmp(&other.range)
+            .then_with(|| self.speaker.cmp(&other.speaker))
+            .then_with(|| self.text.cmp(&other.text))
+            .then_with(|| self.identifier.cmp(&other.identifier))
+            .then_with(|| self.settings.cmp(&other.settings))
+            .then_with(|| self.notes.cmp(&other.notes))
+    }
+}
+impl Display for WebVTTCue {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        self.render(f, &RenderOptions::default())
+    }
+}
+impl WebVTTCue {
+    fn render(&self, f: &mut std::fmt::Formatter<'_>, options: &RenderOptions) -> std::fmt::Result {
+        if let Some(identifier) = &self.identifier {
+            writeln!(f, "{identifier}")?;
+        }
+        match &self.raw_timing {
+            Some((parsed, raw))
+                if options.keep_timing && *parsed == self.range && options.precision == Precision::Milliseconds =>
+            {
+                f.write_str(raw)?
+            }
+            _ => self.range.render(f, options.precision)?,
+        }
+        match &self.settings {
+            Some(settings) => writeln!(f, " {settings}")?,
+            None => writeln!(f)?,
+        }
+        if let Some(speaker) = &self.speaker {
+            match &options.voice_style {
+                VoiceStyle::Tag => write!(f, "<v {}>", escape(speaker))?,
+                VoiceStyle::Class(class) => write!(f, "<v.{class} {}>", escape(speaker))?,
+                VoiceStyle::Plain => {
+                    // without the opening tag a closing one would dangle
+                    return write!(f, "{}: {}", escape(speaker), self.text.replace("</v>", ""));
+                }
+            }
+        }
+        f.write_str(&self.text)
+    }
+}
+
+/// One block of output, as yielded by `WebVTT::blocks`
+enum Block<'a> {
+    Text(&'a str),
+    Source(&'a str),
+    Cue(&'a WebVTTCue, &'a RenderOptions),
+}
+impl Display for Block<'_> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Block::Text(text) => f.write_str(text),
+            // a note can't contain an arrow
+            Block::Source(source) => write!(f, "NOTE source: {}", source.replace("-->", "->")),
+            Block::Cue(cue, options) => cue.render(f, options),
+        }
+    }
+}
+
+/// Ordered by start, then end.
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord)]
+pub struct Timerange (Timestamp, Timestamp);
+impl Timerange {
+    pub fn new(start: Timestamp, end: Timestamp) -> Self {
+        Self(start, end)
+    }
+    /// Parses `start --> end`. Any amount of spaces or tabs can surround
+    /// the arrow, printing always uses a single space.
+    ///
+    /// ```
+    /// # use mergevtt::Timerange;
+    /// for line in ["00:01.000\t-->\t00:02.000", "00:01.000  -->  00:02.000"] {
+    ///     assert_eq!(Timerange::from(line).unwrap().to_string(), "00:01.000 --> 00:02.000");
+    /// }
+    /// ```
+    pub fn from(string: &str) -> Result<Self, WebVTTError> {
+        Self::parse(string, &ParseOptions::default())
+    }
+    /// Like [`Timerange::from`], but with the timestamps read by
+    /// [`Timestamp::parse`].
+    pub fn parse(string: &str, options: &ParseOptions) -> Result<Self, WebVTTError> {
+        let mut elements = string.split_whitespace();
+        let start = elements.next().ok_or(WebVTTError::Parsing(
+            "a starting time".to_owned(),
+            "".to_owned()
+        ))?.to_owned();
+        let start = Timestamp::parse(&start, options)?;
+
+        let sep = elements.next().unwrap_or("");
+        if sep != "-->" {
+            return Err(WebVTTError::Parsing(
+                "-->".to_owned(),
+                sep.to_owned()
+            ));
+        }
+
+        let end = elements.next().ok_or(WebVTTError::Parsing(
+            "a end time".to_owned(),
+            "".to_owned()
+        ))?.to_owned();
+        let end = Timestamp::parse(&end, options)?;
+
+        Ok(Self(start, end))
+    }
+    pub fn start(&self) -> Timestamp {
+        self.0
+    }
+    pub fn end(&self) -> Timestamp {
+        self.1
+    }
+    pub fn validate(&self) -> Result<(), WebVTTError> {
+        if self.0 > self.1 {
+            return Err(WebVTTError::EndBeforeStart(self.clone()));
+        }
+        Ok(())
+    }
+    /// The length of the range, zero if it ends before it starts.
+    pub fn duration(&self) -> Duration {
+        self.1 - self.0
+    }
+    /// Returns whether either end was clamped at zero.
+    pub fn shift(&mut self, delta: Duration, forward: bool) -> bool {
+        let start = self.0.shift(delta, forward);
+        let end = self.1.shift(delta, forward);
+        start || end
+    }
+}
+/// Serializes as `start_ms` and `end_ms` fields
+#[cfg(feature = "serde")]
+impl serde::Serialize for Timerange {
+    fn serialize<S: serde::Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        use serde::ser::SerializeStruct;
+        let mut s = serializer.serialize_struct("Timerange", 2)?;
+        s.serialize_field("start_ms", &self.0)?;
+        s.serialize_field("end_ms", &self.1)?;
+        s.end()
+    }
+}
+/// Deserializes from `start_ms` and `end_ms` fields, rejecting ranges that end
+/// before they start
+#[cfg(feature = "serde")]
+impl<'de> serde::Deserialize<'de> for Timerange {
+    fn deserialize<D: serde::Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        #[derive(serde::Deserialize)]
+        struct Raw {
+            start_ms: Timestamp,
+            end_ms: Timestamp,
+        }
+        let raw = Raw::deserialize(deserializer)?;
+        let range = Self(raw.start_ms, raw.end_ms);
+        range.validate().map_err(serde::de::Error::custom)?;
+        Ok(range)
+    }
+}
+/// Same as [`Timerange::from`]
+///
+/// ```
+/// # use mergevtt::Timerange;
+/// let range: Timerange = "00:01.000 --> 00:02.000".parse().unwrap();
+/// assert_eq!(range.to_string(), "00:01.000 --> 00:02.000");
+/// ```
+impl std::str::FromStr for Timerange {
+    type Err = WebVTTError;
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Self::from(s)
+    }
+}
+impl Timerange {
+    fn render(&self, f: &mut std::fmt::Formatter<'_>, precision: Precision) -> std::fmt::Result {
+        self.0.render(f, precision)?;
+        f.write_str(" --> ")?;
+        self.1.render(f, precision)
+    }
+}
+
+impl Display for Timerange {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        self.render(f, Precision::Milliseconds)
+    }
+}
+
+/// A point in time within a file.
+///
+/// Remembers whether it was written with an hours field, so that `MM:SS.mmm`
+/// inputs are printed back in the same shape. Comparisons only look at the time.
+#[derive(Debug, Clone, Copy)]
+pub struct Timestamp(std::time::Duration, bool);
+impl Timestamp {
+    /// Parses `[HH:]MM:SS.mmm`. Seconds and milliseconds are parsed as
+    /// integers, so printing a parsed timestamp gives back the same text.
+    ///
+    /// The fraction can have any number of digits and is rounded to the
+    /// nearest millisecond, half up. It can also be left out.
+    ///
+    /// ```
+    /// # use mergevtt::Timestamp;
+    /// assert_eq!(Timestamp::from("00:00:01.5").unwrap().to_string(), "00:00:01.500");
+    /// assert_eq!(Timestamp::from("00:00:01.123456").unwrap().to_string(), "00:00:01.123");
+    /// assert_eq!(Timestamp::from("00:00:01.9995").unwrap().to_string(), "00:00:02.000");
+    /// assert_eq!(Timestamp::from("00:00:01").unwrap().to_string(), "00:00:01.000");
+    /// let empty = |s| Timestamp::from(s).unwrap_err().to_string();
+    /// assert_eq!(empty("::5"), "empty hours in timestamp '::5'");
+    /// assert_eq!(empty("01::02"), "empty minutes in timestamp '01::02'");
+    /// assert_eq!(empty("01:02:"), "empty seconds in timestamp '01:02:'");
+    /// for ms in (0..10_000_000).step_by(997) {
+    ///     let formatted = Timestamp::from_millis(ms).to_string();
+    ///     assert_eq!(Timestamp::from(&formatted).unwrap().to_string(), formatted);
+    /// }
+    /// ```
+    pub fn from(string: &str) -> Result<Self, WebVTTError> {
+        let elements: Vec<_> = string.split(':').rev().collect();
+        if elements.len() > 3 {
+            return Err(WebVTTError::Parsing("a timestamp".to_owned(), string.to_owned()));
+        }
+        // report the leftmost empty component, `.500` has empty seconds too
+        let empty = elements.iter().enumerate().rev()
+            .find(|(i, el)| el.is_empty() || (*i == 0 && el.starts_with(['.', ','])));
+        if let Some((i, _)) = empty {
+            let component = ["seconds", "minutes", "hours"][i];
+            return Err(WebVTTError::EmptyComponent(component, string.to_owned()));
+        }
+
+        // SRT-style timestamps use a comma before the milliseconds
+        let seconds = elements[0].replacen(',', ".", 1);
+        let (secs, fraction) = seconds.split_once('.').unwrap_or((&seconds, ""));
+        let secs: u64 = secs
+            .parse()
+            .map_err(|_| WebVTTError::Parsing("a decimal number".to_owned(), elements[0].to_owned()))?;
+        if !fraction.chars().all(|c| c.is_ascii_digit()) {
+            return Err(WebVTTError::Parsing("a decimal number".to_owned(), elements[0].to_owned()));
+        }
+        // pad or cut the fraction to exactly three digits, rounding to the
+        // nearest millisecond by the fourth
+        let mut millis: u64 = format!("{:0<3.3}", fraction)
+            .parse()
+            .map_err(|_| WebVTTError::Parsing("a decimal number".to_owned(), elements[0].to_owned()))?;
+        if fraction.as_bytes().get(3).is_some_and(|&d| d >= b'5') {
+            millis += 1;
+        }
+        let too_large = || WebVTTError::Parsing("a timestamp".to_owned(), string.to_owned());
+        let mut duration = Duration::from_secs(secs)
+            .checked_add(Duration::from_millis(millis))
+            .ok_or_else(too_large)?;
+
+        for (i, el) in elements.iter().enumerate().skip(1) {
+            let el: u64 = el.parse()
+                .map_err(|_| WebVTTError::Parsing("a number".to_owned(), el.to_string()))?;
+            duration = u64::pow(60, i as u32).checked_mul(el)
+                .and_then(|secs| duration.checked_add(Duration::from_secs(secs)))
+                .ok_or_else(too_large)?;
+        }
+
+        Ok(Self(duration, elements.len() > 2))
+    }
+    /// ```
+    /// # use mergevtt::Timestamp;
+    /// assert_eq!(Timestamp::from_millis(1500).as_millis(), 1500);
+    /// assert_eq!(Timestamp::from_millis(1500).to_string(), "00:00:01.500");
+    /// ```
+    pub fn from_millis(millis: u64) -> Self {
+        Self(Duration::from_millis(millis), true)
+    }
+    /// Like [`Timestamp::from_millis`], cut to whole milliseconds.
+    ///
+    /// ```
+    /// # use std::time::Duration;
+    /// # use mergevtt::Timestamp;
+    /// assert_eq!(Timestamp::from_duration(Duration::from_micros(1_500_900)).to_string(), "00:00:01.500");
+    /// assert_eq!(Timestamp::from_duration(Duration::MAX).as_duration().as_secs(), u64::MAX);
+    /// ```
+    pub fn from_duration(duration: Duration) -> Self {
+        Self(Duration::new(duration.as_secs(), duration.subsec_millis() * 1_000_000), true)
+    }
+    pub fn as_millis(&self) -> u128 {
+        self.0.as_millis()
+    }
+    pub fn as_duration(&self) -> Duration {
+        self.0
+    }
+    /// Like [`Timestamp::from`], but with
+    /// [`ParseOptions::lenient_timestamps`] a fourth field is read as
+    /// milliseconds.
+    ///
+    /// ```
+    /// # use mergevtt::{ParseOptions, Timestamp, WebVTT};
+    /// let options = ParseOptions { lenient_timestamps: true, ..ParseOptions::default() };
+    /// assert_eq!(Timestamp::parse("00:00:01:500", &options).unwrap().as_millis(), 1500);
+    /// assert!(Timestamp::parse("00:00:01:500", &ParseOptions::default()).is_err());
+    ///
+    /// let vtt = WebVTT::parse("WEBVTT\n\n00:00:01:500 --> 00:00:02:000\nhi\n", &options).unwrap();
+    /// assert_eq!(vtt.to_string(), "WEBVTT\n\n00:00:01.500 --> 00:00:02.000\nhi\n");
+    /// ```
+    pub fn parse(string: &str, options: &ParseOptions) -> Result<Self, WebVTTError> {
+        if options.lenient_timestamps && string.split(':').count() == 4 {
+            if let Some((time, millis)) = string.rsplit_once(':') {
+                if !millis.is_empty() && millis.chars().all(|c| c.is_ascii_digit()) {
+                    return Self::from(&format!("{time}.{millis}"));
+                }
+            }
+        }
+        Self::from(string)
+    }
+    /// Formats as `HH:MM:SS,mmm` like SubRip expects.
+    pub fn to_srt(&self) -> String {
+        let secs = self.0.as_secs();
+        format!("{:02}:{:02}:{:02},{:03}", secs / (60 * 60), secs / 60 % 60, secs % 60, self.0.subsec_millis())
+    }
+    /// Moves the timestamp `delta` later, or earlier if `forward` is false.
+    ///
+    /// ```
+    /// # use std::time::Duration;
+    /// # use mergevtt::Timestamp;
+    /// let ts = Timestamp::from("00:00:00.500").unwrap();
+    /// assert!(ts.checked_shift(Duration::from_secs(1), false).is_err());
+    /// assert_eq!(ts.checked_shift(Duration::from_millis(500), false).unwrap().to_string(), "00:00:00.000");
+    /// ```
+    pub fn checked_shift(&self, delta: Duration, forward: bool) -> Result<Self, WebVTTError> {
+        match forward {
+            true => Ok(*self + delta),
+            false => self.0.checked_sub(delta)
+                .map(|d| Self(d, self.1))
+                .ok_or(WebVTTError::NegativeTimestamp(*self, delta)),
+        }
+    }
+    fn scale(&mut self, factor: f64) {
+        self.0 = Duration::from_millis((self.0.as_millis() as f64 * factor).round() as u64);
+    }
+    /// Like [`Timestamp::checked_shift`], but clamps at zero instead of
+    /// failing. Returns whether it was clamped.
+    ///
+    /// ```
+    /// # use std::time::Duration;
+    /// # use mergevtt::Timestamp;
+    /// let mut ts = Timestamp::from("00:00:00.500").unwrap();
+    /// assert!(ts.shift(Duration::from_secs(1), false));
+    /// assert_eq!(ts.to_string(), "00:00:00.000");
+    /// ```
+    pub fn shift(&mut self, delta: Duration, forward: bool) -> bool {
+        match self.checked_shift(delta, forward) {
+            Ok(shifted) => {
+                *self = shifted;
+                false
+            },
+            Err(_) => {
+                self.0 = Duration::ZERO;
+                true
+            },
+        }
+    }
+}
+impl PartialEq for Timestamp {
+    fn eq(&self, other: &Self) -> bool {
+        self.0 == other.0
+    }
+}
+impl Eq for Timestamp {}
+impl PartialOrd for Timestamp {
+    fn partial_cmp(&self, other: &Self) -> Option<std::cmp::Ordering> {
+        Some(self.cmp(other))
+    }
+}
+impl Ord for Timestamp {
+    fn cmp(&self, other: &Self) -> std::cmp::Ordering {
+        self.0.cmp(&other.0)
+    }
+}
+/// Serializes as milliseconds
+#[cfg(feature = "serde")]
+impl serde::Serialize for Timestamp {
+    fn serialize<S: serde::Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_u128(self.as_millis())
+    }
+}
+#[cfg(feature = "serde")]
+impl<'de> serde::Deserialize<'de> for Timestamp {
+    fn deserialize<D: serde::Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        u64::deserialize(deserializer).map(Self::from_millis)
+    }
+}
+/// Later by the duration, saturating instead of overflowing
+///
+/// ```
+/// # use std::time::Duration;
+/// # use mergevtt::Timestamp;
+/// let ts = Timestamp::from("00:01.000").unwrap() + Duration::from_millis(500);
+/// assert_eq!(ts.to_string(), "00:01.500");
+/// assert_eq!((Timestamp::from_millis(u64::MAX) + Duration::MAX).as_duration(), Duration::MAX);
+/// ```
+impl std::ops::Add<Duration> for Timestamp {
+    type Output = Timestamp;
+    fn add(self, rhs: Duration) -> Self::Output {
+        Self(self.0.saturating_add(rhs), self.1)
+    }
+}
+/// Earlier by the duration, saturating at zero
+///
+/// ```
+/// # use std::time::Duration;
+/// # use mergevtt::Timestamp;
+/// let ts = Timestamp::from("00:01.000").unwrap();
+/// assert_eq!((ts - Duration::from_millis(250)).to_string(), "00:00.750");
+/// assert_eq!((ts - Duration::from_secs(5)).as_millis(), 0);
+/// ```
+impl std::ops::Sub<Duration> for Timestamp {
+    type Output = Timestamp;
+    fn sub(self, rhs: Duration) -> Self::Output {
+        Self(self.0.saturating_sub(rhs), self.1)
+    }
+}
+/// The time between two timestamps, zero if `rhs` is later
+///
+/// ```
+/// # use std::time::Duration;
+/// # use mergevtt::Timestamp;
+/// let (a, b) = (Timestamp::from_millis(1000), Timestamp::from_millis(2500));
+/// assert_eq!(b - a, Duration::from_millis(1500));
+/// assert_eq!(a - b, Duration::ZERO);
+/// ```
+impl std::ops::Sub for Timestamp {
+    type Output = Duration;
+    fn sub(self, rhs: Self) -> Self::Output {
+        self.0.saturating_sub(rhs.0)
+    }
+}
+/// Same as [`Timestamp::from`]
+///
+/// ```
+/// # use mergevtt::Timestamp;
+/// let ts: Timestamp = "01:02.500".parse().unwrap();
+/// assert_eq!(ts.as_millis(), 62_500);
+/// ```
+impl std::str::FromStr for Timestamp {
+    type Err = WebVTTError;
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Self::from(s)
+    }
+}
+impl Timestamp {
+    /// Rounds to `precision` first, so `00:59.995` can become `01:00.00`
+    fn render(&self, f: &mut std::fmt::Formatter<'_>, precision: Precision) -> std::fmt::Result {
+        let millis = self.0.as_millis();
+        let (secs, fraction) = match precision {
+            Precision::Milliseconds => (millis / 1000, format!(".{:03}", millis % 1000)),
+            Precision::Centiseconds => {
+                let centis = (millis + 5) / 10;
+                (centis / 100, format!(".{:02}", centis % 100))
+            }
+            Precision::Seconds => ((millis + 500) / 1000, String::new()),
+        };
+        if self.1 || secs >= 60 * 60 {
+            write!(f, "{:02}:", secs / (60 * 60))?;
+        }
+        write!(f, "{:02}:{:02}{fraction}", secs / 60 % 60, secs % 60)
+    }
+}
+
+impl Display for Timestamp {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        self.render(f, Precision::Milliseconds)
+    }
+}
@@ -1,182 +1,784 @@
-use std::{fmt::Display, path::{Path, PathBuf}, time::Duration};
+use std::{collections::{BTreeMap, HashMap, HashSet}, fs::File, io::{BufWriter, Read, Write}, path::{Path, PathBuf}, str::FromStr, time::Duration};
 
-use clap::Parser;
-use thiserror::Error;
+use clap::{Parser, ValueEnum};
+use encoding_rs::Encoding;
+use mergevtt::{Error, ParseOptions, Precision, RenderOptions, SortKey, Timestamp, VoiceStyle, WebVTT, WebVTTCue, WebVTTError};
+
+/// Prints a warning to stderr, unless `--quiet` was given
+macro_rules! warn {
+    ($cli:expr, $($arg:tt)*) => {
+        if !$cli.quiet {
+            eprintln!($($arg)*);
+        }
+    };
+}
+
+#[derive(ValueEnum, Debug, Clone, Copy)]
+enum Format {
+    /// WebVTT
+    Vtt,
+    /// SubRip
+    Srt,
+    /// A plain text transcript
+    Txt,
+    /// JSON, with times in milliseconds
+    #[cfg(feature = "serde")]
+    Json,
+}
+
+impl Format {
+    fn extension(self) -> &'static str {
+        match self {
+            Format::Vtt => "vtt",
+            Format::Srt => "srt",
+            Format::Txt => "txt",
+            #[cfg(feature = "serde")]
+            Format::Json => "json",
+        }
+    }
+}
+
+#[derive(ValueEnum, Debug, Clone, Copy, PartialEq)]
+enum TrackKind {
+    Captions,
+    /// Cue texts are chapter titles
+    Chapters,
+    /// Cue texts are data, like JSON
+    Metadata,
+}
+
+#[derive(ValueEnum, Debug, Clone, Copy, PartialEq)]
+enum SortBy {
+    Start,
+    End,
+    Speaker,
+}
+impl From<SortBy> for SortKey {
+    fn from(sort_by: SortBy) -> Self {
+        match sort_by {
+            SortBy::Start => SortKey::Start,
+            SortBy::End => SortKey::End,
+            SortBy::Speaker => SortKey::Speaker,
+        }
+    }
+}
+
+#[derive(ValueEnum, Debug, Clone, Copy, PartialEq)]
+enum TimePrecision {
+    /// Milliseconds, `00:01.234`
+    Ms,
+    /// Centiseconds, `00:01.23`
+    Cs,
+    /// Whole seconds, `00:01`
+    S,
+}
+impl From<TimePrecision> for Precision {
+    fn from(time_precision: TimePrecision) -> Self {
+        match time_precision {
+            TimePrecision::Ms => Precision::Milliseconds,
+            TimePrecision::Cs => Precision::Centiseconds,
+            TimePrecision::S => Precision::Seconds,
+        }
+    }
+}
 
 #[derive(Parser, Debug)]
 #[command(version, about, long_about = None)]
 struct Cli {
+    /// Input files, `-` reads from stdin. Since stdin can only be read once,
+    /// only one `-` is meaningful. Directories stand for the `.vtt` files
+    /// in them, in order of their names.
     #[arg(required = true)]
     files: Vec<PathBuf>,
-    #[arg(long, required = true, value_delimiter = ',')]
+    /// Also take `.vtt` files from directories inside directories
+    #[arg(short, long)]
+    recursive: bool,
+    /// One speaker per file. Defaults to the file names without extension
+    #[arg(long, value_delimiter = ',')]
     speakers: Vec<String>,
+    /// One speaker, given once per file in order. Unlike --speakers, names
+    /// can contain commas
+    #[arg(long, value_name = "NAME", conflicts_with = "speakers")]
+    speaker: Vec<String>,
+    /// Look up speakers in a file of `filename = Speaker Name` lines
+    #[arg(long, value_name = "FILE", conflicts_with_all = ["speakers", "speaker"])]
+    speaker_map: Option<PathBuf>,
+    /// Assign the speakers even to cues that already have a voice tag
+    #[arg(long)]
+    force_speaker: bool,
+    /// Rename speakers that are just a number, e.g. `Speaker {n:02}` turns
+    /// `1` into `Speaker 01`
+    #[arg(long, value_name = "TEMPLATE")]
+    speaker_template: Option<String>,
+    /// What the inputs contain. Speakers are only assigned to captions
+    #[arg(long, value_enum, default_value_t = TrackKind::Captions)]
+    track_kind: TrackKind,
+    /// Don't assign any speakers, just merge and sort the files
+    #[arg(long, conflicts_with_all = ["speakers", "speaker", "speaker_map", "force_speaker"])]
+    no_speaker: bool,
+    /// Move the cues of HLS segments by their X-TIMESTAMP-MAP header
+    #[arg(long)]
+    timestamp_map: bool,
+    /// The inputs are segments of one HLS stream: implies --timestamp-map,
+    /// drops the cues repeated at segment boundaries, and doesn't assign
+    /// speakers unless they are given
+    #[arg(long)]
+    hls: bool,
+    /// Accept cues that end before they start, and cues right after the
+    /// header without a blank line in between
+    #[arg(long)]
+    no_validate: bool,
+    /// Accept whitespace before `WEBVTT` on the first line
+    #[arg(long)]
+    lenient_header: bool,
+    /// The character encoding of the input files, like `windows-1252`
+    #[arg(long, value_parser = parse_encoding, default_value = "utf-8")]
+    encoding: &'static Encoding,
+    /// Read `HH:MM:SS:mmm` timestamps from buggy exporters
+    #[arg(long)]
+    lenient_timestamps: bool,
+    /// Fail on unsorted input files instead of sorting them
+    #[arg(long)]
+    strict_order: bool,
+    /// Sort input files in which cues start earlier than the cue before
+    /// them. This is the default, --keep-backwards turns it off
+    #[arg(long)]
+    autofix: bool,
+    /// Leave cues that start earlier than the cue before them where they
+    /// are, instead of sorting. Only works with a single input file, since
+    /// merging needs sorted files
+    #[arg(long, conflicts_with_all = ["strict_order", "hls", "autofix"])]
+    keep_backwards: bool,
+    /// Read the input files as JSON, as written by `--format json`
+    #[arg(long)]
+    from_json: bool,
+    /// Shift each file by this much, one value per file, e.g. `1.5` or `-00:00:01.500`
+    #[arg(long, value_delimiter = ',', allow_hyphen_values = true)]
+    offset: Vec<Offset>,
+    /// Only check that the input files parse, are sorted, and have valid
+    /// cues, without writing any output
+    #[arg(long)]
+    check: bool,
+    /// Shift the merged file by this much, e.g. `10` or `-00:01.500`
+    #[arg(long, allow_hyphen_values = true)]
+    shift: Option<Offset>,
+    /// Multiply all timestamps by this factor, to correct drift
+    #[arg(long)]
+    scale: Option<f64>,
+    /// Instead of one merged file, write `<speaker>.vtt` files, or whatever
+    /// --format asks for, into this directory, one per speaker
+    #[arg(long, value_name = "DIR", conflicts_with = "output")]
+    split_by_speaker: Option<PathBuf>,
+    /// Write the merged file here instead of to stdout
+    #[arg(short, long)]
+    output: Option<PathBuf>,
+    #[arg(long, value_enum, default_value_t = Format::Vtt)]
+    format: Format,
+    /// Show every speaker in a REGION of their own
+    #[arg(long)]
+    regions: bool,
+    /// Give every speaker a color with a STYLE block
+    #[arg(long)]
+    colorize: bool,
+    /// Write a `Kind:` line into the header, e.g. `captions`
+    #[arg(long)]
+    kind: Option<String>,
+    /// Write a `Language:` line into the header, e.g. `en`
+    #[arg(long)]
+    language: Option<String>,
+    /// Write a NOTE with the input file before every cue
+    #[arg(long)]
+    annotate_source: bool,
+    /// Leave the times out of --format txt
+    #[arg(long)]
+    no_timestamps: bool,
+    /// Order the output by this, before --skip, --limit, and --renumber
+    #[arg(long, value_enum, default_value_t = SortBy::Start)]
+    sort_by: SortBy,
+    /// How speakers are written: `tag` (<v Speaker>), `class:<name>`
+    /// (<v.name Speaker>), or `plain` (Speaker: )
+    #[arg(long, value_parser = parse_voice_style, default_value = "tag")]
+    voice_style: VoiceStyle,
+    /// Print cue times of --format vtt as they were written, unless they
+    /// were changed. May keep timings that aren't valid WebVTT
+    #[arg(long)]
+    keep_timing: bool,
+    /// Round the cue times of --format vtt to this. WebVTT needs `ms`, so
+    /// strict players may reject `cs` and `s`
+    #[arg(long, value_enum, default_value_t = TimePrecision::Ms)]
+    time_precision: TimePrecision,
+    /// Drop cues that end before this time
+    #[arg(long)]
+    start: Option<Timestamp>,
+    /// Drop cues that start after this time
+    #[arg(long)]
+    end: Option<Timestamp>,
+    /// Cut cues that overlap --start or --end to fit
+    #[arg(long)]
+    trim_window: bool,
+    /// Only output cues of this speaker, can be given multiple times
+    #[arg(long, value_name = "NAME")]
+    only_speaker: Vec<String>,
+    /// Drop cues that are exact copies of another one
+    #[arg(long)]
+    dedup: bool,
+    /// Trim whitespace around each line of cue text
+    #[arg(long)]
+    trim_text: bool,
+    /// Trim each line of cue text and turn runs of whitespace into one space
+    #[arg(long)]
+    normalize_whitespace: bool,
+    /// Wrap cue text at spaces to lines of at most this many characters
+    #[arg(long, value_name = "CHARS")]
+    max_line_length: Option<usize>,
+    /// Drop cues shorter than this many milliseconds
+    #[arg(long, value_name = "MS")]
+    min_duration: Option<u64>,
+    /// End each cue before the same speaker's next one starts
+    #[arg(long)]
+    clamp_overlaps: bool,
+    /// Trim overlapping cues so that speakers earlier in this list are
+    /// never talked over, speakers not in it come last
+    #[arg(long, value_name = "SPEAKERS", value_delimiter = ',')]
+    priority: Vec<String>,
+    /// Turn cues of different speakers that overlap into one cue with a
+    /// voice span for each
+    #[arg(long)]
+    combine_overlaps: bool,
+    /// Join same-speaker cues with gaps shorter than this many milliseconds
+    #[arg(long, value_name = "MS")]
+    coalesce: Option<u64>,
+    /// Skip this many cues of the output
+    #[arg(long, value_name = "N", default_value_t = 0)]
+    skip: usize,
+    /// Output at most this many cues
+    #[arg(long, value_name = "N")]
+    limit: Option<usize>,
+    /// Join neighbouring cues of the same speaker with the same text
+    #[arg(long)]
+    collapse_repeats: bool,
+    /// Number the cues 1 to N in output order
+    #[arg(long)]
+    renumber: bool,
+    /// Warn about cues of different speakers that overlap in time
+    #[arg(long)]
+    warn_overlaps: bool,
+    /// Warn about cues with unknown or unbalanced tags
+    #[arg(long)]
+    validate_tags: bool,
+    /// Print silent gaps longer than this to stderr
+    #[arg(long, value_name = "SECONDS")]
+    gap_report: Option<Timestamp>,
+    /// Don't print warnings and reports, only errors
+    #[arg(short, long)]
+    quiet: bool,
+    /// Print statistics about the merged file to stderr
+    #[arg(long)]
+    stats: bool,
 }
 
 fn main() -> Result<(), Box<dyn std::error::Error>> {
-    let cli = Cli::parse();
+    let mut cli = Cli::parse();
+    cli.files = expand_directories(&cli.files, cli.recursive)?;
+    if cli.files.is_empty() {
+        return Err("no .vtt files in the given directories".into());
+    }
 
-    if cli.speakers.len() != cli.files.len() {
+    if cli.check {
+        return check(&cli);
+    }
+    if cli.keep_backwards && cli.files.len() > 1 {
+        return Err("--keep-backwards only works with a single input file".into());
+    }
+
+    let no_speaker = cli.no_speaker
+        || cli.track_kind != TrackKind::Captions
+        || (cli.hls && cli.speaker_map.is_none() && cli.speaker.is_empty() && cli.speakers.is_empty());
+    let speakers = if no_speaker {
+        vec![]
+    } else if let Some(map) = &cli.speaker_map {
+        let map = load_speaker_map(map)
+            .map_err(|e| format!("while reading {}: {}", map.to_string_lossy(), e))?;
+        cli.files.iter().map(|path| lookup_speaker(&map, path)).collect::<Result<Vec<_>, _>>()?
+    } else if !cli.speaker.is_empty() {
+        cli.speaker.clone()
+    } else if !cli.speakers.is_empty() {
+        cli.speakers.clone()
+    } else {
+        cli.files.iter().map(|path| speaker_from_filename(path)).collect::<Result<Vec<_>, _>>()?
+    };
+    if !no_speaker && speakers.len() != cli.files.len() {
         return Err("differing number of speakers and files. every file needs one speaker defined".into());
     }
+    let speakers: Vec<String> = speakers.iter().map(|s| s.trim().to_owned()).collect();
+    if let Some(i) = speakers.iter().position(|s| s.is_empty()) {
+        return Err(format!("empty speaker name for {}", cli.files[i].to_string_lossy()).into());
+    }
+    if !cli.offset.is_empty() && cli.offset.len() != cli.files.len() {
+        return Err("differing number of offsets and files. every file needs one offset defined".into());
+    }
 
-    let mut vtt = WebVTT::new();
-    for (speaker, path) in cli.speakers.iter().zip(cli.files.iter()) {
-        let mut file = load_vtt(path).map_err(|e| format!("while parsing {}: {}", path.to_string_lossy(), e))?;
-        let orgiginal = file.clone();
-        file.sort();
-        if file != orgiginal {
-            eprintln!("unsorted: {}", path.to_string_lossy());
+    let mut files = vec![];
+    for (i, path) in cli.files.iter().enumerate() {
+        let mut file = load_vtt(path, cli.from_json, cli.encoding, &parse_options(&cli)).map_err(|e| format!("while parsing {}: {}", path.to_string_lossy(), e))?;
+        file.set_source(&path.to_string_lossy());
+        if !cli.no_validate {
+            file.validate().map_err(|e| format!("while validating {}: {}", path.to_string_lossy(), e))?;
+        }
+        // --hls leaves this to `WebVTT::concat_segments`
+        if cli.timestamp_map && !cli.hls {
+            file.apply_timestamp_map().map_err(|e| format!("while reading the timestamp map of {}: {}", path.to_string_lossy(), e))?;
+        }
+        if let Some(offset) = cli.offset.get(i) {
+            let clamped = file.shift(offset.delta, offset.forward);
+            if clamped > 0 {
+                warn!(cli, "clamped {} cues at zero: {}", clamped, path.to_string_lossy());
+            }
+        }
+        match speakers.get(i) {
+            Some(speaker) if cli.force_speaker => file.force_speaker_for_all_lines(speaker),
+            Some(speaker) => file.set_speaker_for_all_lines(speaker),
+            None => {}
+        }
+        let backwards = file.backwards_starts();
+        for (a, b) in backwards.iter() {
+            let message = format!("backwards: {}: {}", path.to_string_lossy(), describe_unsorted(a, b));
+            if cli.strict_order {
+                return Err(message.into());
+            }
+            warn!(cli, "{message}");
+        }
+        let has_backwards = !backwards.is_empty();
+        // cues going backwards are unsorted as well, but were reported already
+        if let Some((a, b)) = file.first_unsorted().filter(|_| !has_backwards) {
+            let message = format!("unsorted: {}: {}", path.to_string_lossy(), describe_unsorted(a, b));
+            if cli.strict_order {
+                return Err(message.into());
+            }
+            warn!(cli, "{message}");
+        }
+        let leave_as_is = has_backwards && cli.keep_backwards;
+        if !leave_as_is && !file.is_sorted() {
+            file.sort();
+        }
+        files.push(file);
+    }
+    // merging would sort the single file after all
+    let mut vtt = match files.pop() {
+        Some(file) if cli.keep_backwards => file,
+        last if cli.hls => WebVTT::concat_segments(files.into_iter().chain(last).collect())
+            .map_err(|e| format!("while stitching the HLS segments: {}", e))?,
+        last => WebVTT::merge_all(files.into_iter().chain(last).collect()),
+    };
+    if let Some(template) = &cli.speaker_template {
+        vtt.apply_speaker_template(template).map_err(|e| e.to_string())?;
+        // the new names can order cues that start together differently
+        vtt.sort();
+    }
+    if let Some(factor) = cli.scale {
+        vtt.scale(factor).map_err(|e| e.to_string())?;
+    }
+    if let Some(shift) = cli.shift {
+        let clamped = vtt.shift(shift.delta, shift.forward);
+        if clamped > 0 {
+            warn!(cli, "clamped {} cues at zero", clamped);
         }
-        file.set_speaker_for_all_lines(speaker);
-        vtt.merge_with(file);
     }
 
-    println!("{vtt}");
-    Ok(())
-}
-
-fn load_vtt(file: &Path) -> Result<WebVTT, Box<dyn std::error::Error>> {
-    Ok(WebVTT::from(&std::fs::read_to_string(file)?)?)
-}
-
-#[derive(Debug, Error)]
-enum WebVTTError {
-    #[error("parsing error: expected {0}, got '{1}'")]
-    Parsing(String, String),
-}
+    if cli.start.is_some() || cli.end.is_some() {
+        vtt.retain_window(
+            cli.start.unwrap_or(Timestamp::from_millis(0)),
+            cli.end.unwrap_or(Timestamp::from_millis(u64::MAX)),
+            cli.trim_window,
+        );
+    }
+    if !cli.only_speaker.is_empty() {
+        vtt.retain_speakers(&cli.only_speaker);
+    }
+    if cli.trim_text {
+        vtt.trim_text();
+    }
+    if cli.normalize_whitespace {
+        vtt.normalize_whitespace();
+    }
+    if let Some(max) = cli.max_line_length {
+        vtt.wrap_text(max);
+    }
+    if let Some(ms) = cli.min_duration {
+        let dropped = vtt.retain_min_duration(Duration::from_millis(ms));
+        if dropped > 0 {
+            warn!(cli, "dropped {} cues shorter than {}ms", dropped, ms);
+        }
+    }
+    if cli.dedup {
+        vtt.dedup();
+    }
+    if cli.clamp_overlaps {
+        let clamped = vtt.clamp_overlaps();
+        if clamped > 0 {
+            warn!(cli, "clamped {} overlapping cues", clamped);
+        }
+    }
+    if !cli.priority.is_empty() {
+        let priority: Vec<String> = cli.priority.iter().map(|s| s.trim().to_owned()).collect();
+        let trimmed = vtt.resolve_overlaps(&priority);
+        if trimmed > 0 {
+            warn!(cli, "trimmed {} cues under higher priority speakers", trimmed);
+        }
+    }
+    if cli.combine_overlaps {
+        let combined = vtt.combine_overlaps();
+        if combined > 0 {
+            warn!(cli, "combined {} overlapping cues", combined);
+        }
+    }
+    if let Some(ms) = cli.coalesce {
+        vtt.coalesce(Duration::from_millis(ms));
+    }
+    if cli.collapse_repeats {
+        vtt.collapse_repeats();
+    }
+    if cli.sort_by != SortBy::Start {
+        vtt.sort_by(cli.sort_by.into());
+    }
+    if cli.skip > 0 || cli.limit.is_some() {
+        vtt.paginate(cli.skip, cli.limit);
+    }
+    if cli.renumber {
+        vtt.renumber();
+    }
+    if let Some(kind) = &cli.kind {
+        vtt.set_header_field("Kind", kind);
+    }
+    if let Some(language) = &cli.language {
+        vtt.set_header_field("Language", language);
+    }
+    if cli.regions {
+        vtt.assign_regions();
+    }
+    if cli.colorize {
+        vtt.colorize_speakers();
+    }
+    vtt.set_render_options(RenderOptions {
+        voice_style: cli.voice_style.clone(),
+        annotate_source: cli.annotate_source,
+        precision: cli.time_precision.into(),
+        keep_timing: cli.keep_timing,
+    });
 
-#[derive(Debug, Clone, PartialEq, Eq)]
-struct WebVTT (Vec<WebVTTCue>);
-impl WebVTT {
-    pub fn new() -> Self { Self(vec![]) }
-    pub fn from(string: &str) -> Result<Self, WebVTTError> {
-        if !string.starts_with("WEBVTT") {
-            return Err(WebVTTError::Parsing(
-                "WEBVTT".to_owned(),
-                string.lines().next().unwrap_or("").to_owned(),
-            ));
+    // the reports need the cues in time order
+    let mut sorted;
+    let timeline = match cli.sort_by {
+        SortBy::Start => &vtt,
+        _ => {
+            sorted = vtt.clone();
+            sorted.sort();
+            &sorted
+        },
+    };
+    if cli.warn_overlaps {
+        for (a, b) in timeline.overlaps() {
+            warn!(
+                cli,
+                "overlap: {} ({}) and {} ({})",
+                a.speaker().unwrap_or("<none>"), a.timerange(),
+                b.speaker().unwrap_or("<none>"), b.timerange(),
+            );
         }
-        let string = &string["WEBVTT".len()..];
+    }
 
-        let mut lines: Vec<WebVTTCue> = vec![];
+    if cli.validate_tags {
+        for (cue, problem) in vtt.tag_problems() {
+            warn!(cli, "tags: {}: {}", cue.timerange(), problem);
+        }
+    }
 
-        let mut range: Option<Timerange> = None;
-        for line in string.lines() {
-            // eprintln!("Parsing: {}", line);
-            if line.trim().is_empty() {
-                range = None;
-                continue;
-            }
-            match range {
-                None => {
-                    range = Some(Timerange::from(line)?);
-                    continue;
-                },
-                Some(ref range) => lines.push(WebVTTCue::from(range, line)?),
-            }
+    if let Some(min) = cli.gap_report {
+        for gap in timeline.gaps(min.as_duration()) {
+            warn!(cli, "gap: {} ({:.3}s)", gap, gap.duration().as_secs_f64());
         }
-        Ok(Self(lines))
     }
-    pub fn sort(&mut self) {
-        self.0.sort_by_key(|l| l.0.0);
+    if cli.stats {
+        print_stats(timeline);
     }
-    pub fn set_speaker_for_all_lines(&mut self, speaker: &str) {
-        for l in self.0.iter_mut() {
-            l.1 = Some(speaker.to_owned());
+
+    if let Some(dir) = &cli.split_by_speaker {
+        let written = split_by_speaker(&vtt, dir, &cli)?;
+        warn!(cli, "wrote {} files to {}", written, dir.to_string_lossy());
+        let without_speaker = vtt.cues().filter(|l| l.speaker().is_none()).count();
+        if without_speaker > 0 {
+            warn!(cli, "left out {} cues without a speaker", without_speaker);
         }
+        return Ok(());
     }
-    pub fn merge_with(&mut self, other: WebVTT) {
-        self.0.extend(other.0);
-        self.sort();
+
+    match &cli.output {
+        Some(path) => {
+            let write = || {
+                let mut w = BufWriter::new(File::create(path)?);
+                write_output(&vtt, &cli, &mut w)?;
+                w.flush()
+            };
+            write().map_err(|e| format!("while writing {}: {}", path.to_string_lossy(), e))?
+        },
+        None => write_output(&vtt, &cli, &mut std::io::stdout().lock())?,
     }
+    Ok(())
 }
-impl Display for WebVTT {
-    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-        f.write_str("WEBVTT\n")?;
-        for l in self.0.iter() {
-            Display::fmt(&l, f)?;
-        }
-        Ok(())
+
+/// Writes one file per speaker, returns how many were written
+fn split_by_speaker(vtt: &WebVTT, dir: &Path, cli: &Cli) -> Result<usize, Box<dyn std::error::Error>> {
+    std::fs::create_dir_all(dir)
+        .map_err(|e| format!("while creating {}: {}", dir.to_string_lossy(), e))?;
+    let speakers: Vec<String> = vtt.speakers().into_iter().map(str::to_owned).collect();
+    let mut taken = HashSet::new();
+    for speaker in speakers.iter() {
+        let mut file = vtt.clone();
+        file.retain_speakers(std::slice::from_ref(speaker));
+        // speakers can contain characters that aren't allowed in file names
+        let name: String = speaker.chars()
+            .map(|c| if std::path::is_separator(c) || c == '\0' { '_' } else { c })
+            .collect();
+        // `a/b` and `a_b` end up with the same name, and some file systems
+        // don't tell `A` from `a`
+        let name = (1..)
+            .map(|n| if n == 1 { name.clone() } else { format!("{name}-{n}") })
+            .find(|name| taken.insert(name.to_lowercase()))
+            .unwrap();
+        let path = dir.join(format!("{name}.{}", cli.format.extension()));
+        let write = || {
+            let mut w = BufWriter::new(File::create(&path)?);
+            write_output(&file, cli, &mut w)?;
+            w.flush()
+        };
+        write().map_err(|e| format!("while writing {}: {}", path.to_string_lossy(), e))?;
     }
+    Ok(speakers.len())
 }
 
-#[derive(Debug, Clone, PartialEq, Eq)]
-struct WebVTTCue (Timerange, Option<String>, String);
-impl WebVTTCue {
-    pub fn from(range: &Timerange, string: &str) -> Result<Self, WebVTTError> {
-        Ok(Self(range.to_owned(), None, string.to_owned()))
+fn write_output<W: Write>(vtt: &WebVTT, cli: &Cli, w: &mut W) -> std::io::Result<()> {
+    match cli.format {
+        Format::Vtt => vtt.write_to(w),
+        Format::Srt => w.write_all(vtt.to_srt().as_bytes()),
+        Format::Txt => w.write_all(vtt.to_transcript(!cli.no_timestamps).as_bytes()),
+        #[cfg(feature = "serde")]
+        Format::Json => {
+            serde_json::to_writer_pretty(&mut *w, vtt)?;
+            writeln!(w)
+        },
     }
 }
-impl Display for WebVTTCue {
-    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-        writeln!(f, "\n{}", self.0)?;
-        if let Some(speaker) = &self.1 {
-            write!(f, "<v {speaker}>")?;
+
+fn parse_options(cli: &Cli) -> ParseOptions {
+    ParseOptions {
+        allow_missing_blank_line: cli.no_validate,
+        lenient_header: cli.lenient_header,
+        lenient_timestamps: cli.lenient_timestamps,
+    }
+}
+
+fn check(cli: &Cli) -> Result<(), Box<dyn std::error::Error>> {
+    let mut invalid = 0;
+    for path in cli.files.iter() {
+        let mut problems = vec![];
+        match load_vtt(path, cli.from_json, cli.encoding, &parse_options(cli)) {
+            Ok(file) => {
+                if !cli.no_validate {
+                    if let Err(e) = file.validate() {
+                        problems.push(e.to_string());
+                    }
+                }
+                let backwards = file.backwards_starts();
+                for (a, b) in backwards.iter() {
+                    problems.push(format!("backwards: {}", describe_unsorted(a, b)));
+                }
+                if let Some((a, b)) = file.first_unsorted().filter(|_| backwards.is_empty()) {
+                    problems.push(format!("unsorted: {}", describe_unsorted(a, b)));
+                }
+            },
+            Err(e) => problems.push(e.to_string()),
         }
-        writeln!(f, "{}", self.2)
+        for problem in problems.iter() {
+            eprintln!("{}: {}", path.to_string_lossy(), problem);
+        }
+        if !problems.is_empty() {
+            invalid += 1;
+        }
+    }
+    if invalid > 0 {
+        return Err(format!("{} of {} files have problems", invalid, cli.files.len()).into());
     }
+    Ok(())
 }
 
-#[derive(Debug, Clone, PartialEq, Eq)]
-struct Timerange (Timestamp, Timestamp);
-impl Timerange {
-    pub fn from(string: &str) -> Result<Self, WebVTTError> {
-        let mut elements = string.split(' ');
-        let start = elements.next().ok_or(WebVTTError::Parsing(
-            "a starting time".to_owned(),
-            "".to_owned()
-        ))?.to_owned();
-        let start = Timestamp::from(&start)?;
+fn describe_unsorted(a: &WebVTTCue, b: &WebVTTCue) -> String {
+    format!("cue at {} comes after cue at {}", b.timerange(), a.timerange())
+}
 
-        let sep = elements.next().unwrap_or("");
-        if sep != "-->" {
-            return Err(WebVTTError::Parsing(
-                "-->".to_owned(),
-                sep.to_owned()
-            ));
-        }
+fn print_stats(vtt: &WebVTT) {
+    let mut speakers: BTreeMap<&str, (usize, Duration)> = BTreeMap::new();
+    for cue in vtt.cues() {
+        let (count, duration) = speakers.entry(cue.speaker().unwrap_or("<none>")).or_default();
+        *count += 1;
+        *duration += cue.timerange().duration();
+    }
+    eprintln!("cues: {}", vtt.len());
+    for (speaker, (count, duration)) in speakers {
+        eprintln!("  {speaker}: {count} cues, {}", Timestamp::from_duration(duration));
+    }
+    if let Some(first) = vtt.cues().map(|c| c.timerange().start()).min() {
+        eprintln!("earliest: {first}");
+    }
+    if let Some(last) = vtt.cues().map(|c| c.timerange().end()).max() {
+        eprintln!("latest: {last}");
+    }
+    eprintln!("overlaps: {}", vtt.overlaps().len());
+}
 
-        let end = elements.next().ok_or(WebVTTError::Parsing(
-            "a end time".to_owned(),
-            "".to_owned()
-        ))?.to_owned();
-        let end = Timestamp::from(&end)?;
+/// Replaces directories with the `.vtt` files inside them, sorted by name
+fn expand_directories(paths: &[PathBuf], recursive: bool) -> Result<Vec<PathBuf>, String> {
+    let mut files = vec![];
+    for path in paths {
+        if !path.is_dir() {
+            files.push(path.clone());
+            continue;
+        }
+        let mut entries = std::fs::read_dir(path)
+            .and_then(|entries| entries.map(|e| e.map(|e| e.path())).collect::<Result<Vec<_>, _>>())
+            .map_err(|e| format!("while reading {}: {}", path.to_string_lossy(), e))?;
+        entries.sort();
+        for entry in entries {
+            if entry.is_dir() {
+                if recursive {
+                    files.extend(expand_directories(&[entry], recursive)?);
+                }
+            } else if entry.extension().is_some_and(|e| e.eq_ignore_ascii_case("vtt")) {
+                files.push(entry);
+            }
+        }
+    }
+    Ok(files)
+}
 
-        Ok(Self(start, end))
+fn speaker_from_filename(file: &Path) -> Result<String, String> {
+    match without_gz(file).file_stem() {
+        Some(stem) if file != Path::new("-") => Ok(stem.to_string_lossy().into_owned()),
+        _ => Err(format!("can't derive a speaker from {}, use --speakers", file.to_string_lossy())),
     }
 }
-impl Display for Timerange {
-    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-        write!(f, "{} --> {}", self.0, self.1)
+
+/// The path without a trailing `.gz`
+fn without_gz(file: &Path) -> PathBuf {
+    match file.extension() {
+        Some(extension) if extension.eq_ignore_ascii_case("gz") => file.with_extension(""),
+        _ => file.to_owned(),
     }
 }
 
-#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
-struct Timestamp(std::time::Duration);
-impl Timestamp {
-    pub fn from(string: &str) -> Result<Self, WebVTTError> {
-        let elements: Vec<_> = string.split(':').rev().collect();
-        if elements.is_empty() {
-            return Err(WebVTTError::Parsing("a timestamp".to_owned(), string.to_owned()));
+/// Reads `filename = Speaker Name` lines, ignoring blank lines and `#` comments
+fn load_speaker_map(file: &Path) -> Result<HashMap<String, String>, Box<dyn std::error::Error>> {
+    let mut map = HashMap::new();
+    for (i, line) in std::fs::read_to_string(file)?.lines().enumerate() {
+        let line = line.trim();
+        if line.is_empty() || line.starts_with('#') {
+            continue;
         }
+        let (filename, speaker) = line.split_once('=')
+            .ok_or_else(|| format!("line {}: expected `filename = Speaker Name`, got '{}'", i + 1, line))?;
+        map.insert(filename.trim().to_owned(), speaker.trim().to_owned());
+    }
+    Ok(map)
+}
 
-        let secs: f32 = elements[0]
-            .parse()
-            .map_err(|_| WebVTTError::Parsing("a decimal number".to_owned(), elements[0].to_owned()))?;
-        let mut duration = Duration::from_secs_f32(secs);
+/// Finds the speaker by the path as given, or else by its file name
+fn lookup_speaker(map: &HashMap<String, String>, file: &Path) -> Result<String, String> {
+    let file_name = file.file_name().map(|n| n.to_string_lossy());
+    map.get(file.to_string_lossy().as_ref())
+        .or_else(|| file_name.and_then(|n| map.get(n.as_ref())))
+        .cloned()
+        .ok_or_else(|| format!("no speaker for {} in the speaker map", file.to_string_lossy()))
+}
 
-        for (i, el) in elements.iter().enumerate().skip(1) {
-            let el: u64 = el.parse()
-                .map_err(|_| WebVTTError::Parsing("a number".to_owned(), el.to_string()))?;
-            duration += Duration::from_secs(u64::pow(60, i as u32) * el);
+fn load_vtt(file: &Path, from_json: bool, encoding: &'static Encoding, options: &ParseOptions) -> Result<WebVTT, Error> {
+    let bytes = if file == Path::new("-") {
+        let mut bytes = vec![];
+        std::io::stdin().read_to_end(&mut bytes)?;
+        bytes
+    } else {
+        std::fs::read(file)?
+    };
+    // gzip files start with these magic bytes
+    let bytes = if bytes.starts_with(&[0x1f, 0x8b]) {
+        let mut decompressed = vec![];
+        flate2::read::GzDecoder::new(bytes.as_slice()).read_to_end(&mut decompressed)?;
+        decompressed
+    } else {
+        bytes
+    };
+    let string = if encoding == encoding_rs::UTF_8 {
+        String::from_utf8(bytes)?
+    } else {
+        let (string, malformed) = encoding.decode_without_bom_handling(&bytes);
+        if malformed {
+            return Err(Error::Decoding(encoding.name()));
         }
+        string.into_owned()
+    };
+    // `name.srt.gz` is detected by the `.srt`
+    let file = without_gz(file);
+    if from_json {
+        #[cfg(feature = "serde")]
+        return Ok(WebVTT::from_json(&string)?);
+        #[cfg(not(feature = "serde"))]
+        return Err(Error::Unsupported("JSON input requires the serde feature"));
+    }
+    if is_srt(&file, &string) {
+        Ok(WebVTT::from_srt(&string)?)
+    } else {
+        Ok(WebVTT::parse(&string, options)?)
+    }
+}
+
+/// SubRip files either have a `.srt` extension or start with a cue number
+fn is_srt(file: &Path, string: &str) -> bool {
+    if let Some(extension) = file.extension() {
+        return extension.eq_ignore_ascii_case("srt");
+    }
+    let first_line = string.lines().find(|l| !l.trim().is_empty()).unwrap_or("");
+    first_line.trim_start_matches('\u{feff}').trim().parse::<usize>().is_ok()
+}
+
+fn parse_encoding(s: &str) -> Result<&'static Encoding, String> {
+    Encoding::for_label(s.as_bytes()).ok_or_else(|| format!("unknown encoding '{s}'"))
+}
 
-        Ok(Self(duration))
+fn parse_voice_style(s: &str) -> Result<VoiceStyle, String> {
+    match s {
+        "tag" => Ok(VoiceStyle::Tag),
+        "plain" => Ok(VoiceStyle::Plain),
+        _ => match s.strip_prefix("class:") {
+            Some(class) if !class.is_empty() && !class.contains(|c: char| c.is_whitespace() || c == '.' || c == '>') => {
+                Ok(VoiceStyle::Class(class.to_owned()))
+            }
+            Some(_) => Err(format!("invalid class name in '{s}'")),
+            None => Err(format!("expected tag, class:<name>, or plain, got '{s}'")),
+        },
     }
 }
-impl Display for Timestamp {
-    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-        let secs = self.0.as_secs();
-        write!(f, "{:02}:{:02}:{:02}.{:03}", secs / (60 * 60), secs / 60 % 60, secs % 60, self.0.subsec_millis())
+
+/// A signed time shift
+#[derive(Debug, Clone, Copy)]
+struct Offset {
+    delta: Duration,
+    forward: bool,
+}
+impl FromStr for Offset {
+    type Err = WebVTTError;
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let (s, forward) = match s.strip_prefix('-') {
+            Some(s) => (s, false),
+            None => (s.strip_prefix('+').unwrap_or(s), true),
+        };
+        Ok(Self { delta: s.parse::<Timestamp>()?.as_duration(), forward })
     }
 }
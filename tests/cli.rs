@@ -0,0 +1,232 @@
+mod common;
+
+use std::{io::Write, process::{Command, Output, Stdio}};
+
+use common::fixture;
+
+/// Runs the binary in the crate directory and checks that it succeeded
+fn run(args: &[&str]) -> Output {
+    let output = Command::new(env!("CARGO_BIN_EXE_mergevtt"))
+        .current_dir(env!("CARGO_MANIFEST_DIR"))
+        .args(args)
+        .output()
+        .unwrap();
+    assert!(output.status.success(), "{}", String::from_utf8_lossy(&output.stderr));
+    output
+}
+
+/// Runs the binary and returns its stdout
+fn mergevtt(args: &[&str]) -> String {
+    String::from_utf8(run(args).stdout).unwrap()
+}
+
+#[test]
+fn chapters_pass_through() {
+    let output = mergevtt(&["--track-kind", "chapters", "tests/fixtures/chapters.vtt"]);
+    assert_eq!(output, fixture("chapters.vtt"));
+}
+
+#[test]
+fn captions_get_speakers() {
+    let output = mergevtt(&["tests/fixtures/chapters.vtt"]);
+    assert!(output.contains("\n<v chapters>Introduction\n"), "{output}");
+}
+
+#[test]
+fn backwards_cues_get_sorted() {
+    let output = run(&["--no-speaker", "tests/fixtures/identifiers.vtt", "tests/fixtures/backwards.vtt"]);
+    let stdout = String::from_utf8(output.stdout).unwrap();
+    let order: Vec<_> = ["first", "Welcome", "second", "Thanks"].iter().map(|t| stdout.find(t).unwrap()).collect();
+    assert!(order.is_sorted(), "{stdout}");
+    let stderr = String::from_utf8(output.stderr).unwrap();
+    assert_eq!(stderr.matches("backwards: tests/fixtures/backwards.vtt").count(), 1, "{stderr}");
+    assert!(!stderr.contains("unsorted"), "{stderr}");
+    let autofix = mergevtt(&["--no-speaker", "--autofix", "tests/fixtures/identifiers.vtt", "tests/fixtures/backwards.vtt"]);
+    assert_eq!(autofix, stdout);
+}
+
+#[test]
+fn check_reports_backwards_cues_once() {
+    let output = Command::new(env!("CARGO_BIN_EXE_mergevtt"))
+        .current_dir(env!("CARGO_MANIFEST_DIR"))
+        .args(["--check", "tests/fixtures/backwards.vtt"])
+        .output()
+        .unwrap();
+    assert!(!output.status.success());
+    let stderr = String::from_utf8(output.stderr).unwrap();
+    assert_eq!(stderr.lines().filter(|l| l.starts_with("tests/fixtures/backwards.vtt: ")).count(), 1, "{stderr}");
+}
+
+#[test]
+fn keep_backwards_leaves_a_single_file() {
+    let output = mergevtt(&["--no-speaker", "--keep-backwards", "tests/fixtures/backwards.vtt"]);
+    assert_eq!(output, fixture("backwards.vtt"));
+    let merged = Command::new(env!("CARGO_BIN_EXE_mergevtt"))
+        .current_dir(env!("CARGO_MANIFEST_DIR"))
+        .args(["--keep-backwards", "tests/fixtures/identifiers.vtt", "tests/fixtures/backwards.vtt"])
+        .output()
+        .unwrap();
+    assert!(!merged.status.success());
+}
+
+#[test]
+fn quiet_hides_warnings() {
+    let stderr = |args: &[&str]| String::from_utf8(run(args).stderr).unwrap();
+    let args = ["--no-speaker", "--gap-report", "0.1", "tests/fixtures/identifiers.vtt", "tests/fixtures/backwards.vtt"];
+    let warnings = stderr(&args);
+    assert!(warnings.contains("backwards: tests/fixtures/backwards.vtt"), "{warnings}");
+    assert!(warnings.contains("gap: "), "{warnings}");
+    assert_eq!(stderr(&[&["--quiet"], &args[..]].concat()), "");
+}
+
+#[test]
+fn latin1_input() {
+    let output = mergevtt(&["--encoding", "latin1", "tests/fixtures/latin1.vtt"]);
+    assert!(output.contains("\n<v Renée>Chère Soña, ça va?\n"), "{output}");
+    let utf8 = Command::new(env!("CARGO_BIN_EXE_mergevtt"))
+        .current_dir(env!("CARGO_MANIFEST_DIR"))
+        .arg("tests/fixtures/latin1.vtt")
+        .output()
+        .unwrap();
+    assert!(!utf8.status.success());
+}
+
+#[test]
+fn time_precision() {
+    let precision = |p| mergevtt(&["--no-speaker", "--time-precision", p, "tests/fixtures/identifiers.vtt"]);
+    assert_eq!(precision("ms"), fixture("identifiers.vtt"));
+    assert!(precision("cs").contains("\n00:00:04.50 --> 00:00:06.00\n"));
+    assert!(precision("s").contains("\n00:00:05 --> 00:00:06\n"));
+}
+
+#[test]
+fn vtt_and_srt_merge() {
+    let output = mergevtt(&["--speaker", "Alice", "--speaker", "Carol", "tests/fixtures/identifiers.vtt", "tests/fixtures/carol.srt"]);
+    assert_eq!(output, "\
+WEBVTT
+
+intro
+00:00:01.000 --> 00:00:04.000
+<v Alice>Welcome back to the show.
+
+00:00:02.000 --> 00:00:03.500
+<v Carol>Me too.
+
+2
+00:00:04.500 --> 00:00:06.000
+<v Bob>Thanks for having me.
+
+00:00:05.000 --> 00:00:07.000
+<v Carol>Agreed.
+");
+}
+
+#[test]
+fn speakers_are_trimmed() {
+    let output = mergevtt(&["--speakers", "Alice , Bob", "tests/fixtures/chapters.vtt", "tests/fixtures/carol.srt"]);
+    assert!(output.contains("\n<v Alice>Introduction\n"), "{output}");
+    assert!(output.contains("\n<v Bob>Me too.\n"), "{output}");
+}
+
+#[test]
+fn gzipped_input() {
+    let output = mergevtt(&["--no-speaker", "tests/fixtures/identifiers.vtt.gz"]);
+    assert_eq!(output, fixture("identifiers.vtt"));
+    // the inner extension makes it SubRip
+    let output = mergevtt(&["--speaker", "Carol", "tests/fixtures/carol.srt.gz"]);
+    assert!(output.contains("\n00:00:02.000 --> 00:00:03.500\n<v Carol>Me too.\n"), "{output}");
+}
+
+#[test]
+fn gzipped_input_names_the_speaker() {
+    let output = mergevtt(&["tests/fixtures/carol.srt.gz"]);
+    assert!(output.contains("\n<v carol>Me too.\n"), "{output}");
+}
+
+#[test]
+fn gzipped_stdin() {
+    // without a file name only the magic bytes tell it is gzip
+    let mut child = Command::new(env!("CARGO_BIN_EXE_mergevtt"))
+        .args(["--no-speaker", "-"])
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .spawn()
+        .unwrap();
+    let gzipped = std::fs::read(concat!(env!("CARGO_MANIFEST_DIR"), "/tests/fixtures/identifiers.vtt.gz")).unwrap();
+    child.stdin.take().unwrap().write_all(&gzipped).unwrap();
+    let output = child.wait_with_output().unwrap();
+    assert!(output.status.success());
+    assert_eq!(String::from_utf8(output.stdout).unwrap(), fixture("identifiers.vtt"));
+}
+
+#[test]
+fn split_by_speaker_keeps_colliding_names_apart() {
+    let dir = std::env::temp_dir().join(format!("mergevtt-split-{}", std::process::id()));
+    std::fs::create_dir_all(&dir).unwrap();
+    let input = dir.join("input.vtt");
+    std::fs::write(&input, "WEBVTT\n\n00:01.000 --> 00:02.000\n<v a/b>one\n\n00:02.000 --> 00:03.000\n<v a_b>two\n\n00:03.000 --> 00:04.000\nthree\n").unwrap();
+    let out = dir.join("out");
+    let output = run(&["--no-speaker", "--split-by-speaker", out.to_str().unwrap(), input.to_str().unwrap()]);
+    let stderr = String::from_utf8(output.stderr).unwrap();
+    assert!(stderr.contains("wrote 2 files"), "{stderr}");
+    assert!(stderr.contains("left out 1 cues without a speaker"), "{stderr}");
+    let read = |name| std::fs::read_to_string(out.join(name)).unwrap();
+    let (first, second) = (read("a_b.vtt"), read("a_b-2.vtt"));
+    std::fs::remove_dir_all(&dir).unwrap();
+    assert!(first.contains("<v a/b>one") && !first.contains("two"), "{first}");
+    assert!(second.contains("<v a_b>two") && !second.contains("one"), "{second}");
+}
+
+#[test]
+fn split_by_speaker_follows_the_format() {
+    let out = std::env::temp_dir().join(format!("mergevtt-split-srt-{}", std::process::id()));
+    run(&["--no-speaker", "--format", "srt", "--split-by-speaker", out.to_str().unwrap(), "tests/fixtures/identifiers.vtt"]);
+    let alice = std::fs::read_to_string(out.join("Alice.srt"));
+    let vtt = out.join("Alice.vtt").exists();
+    std::fs::remove_dir_all(&out).unwrap();
+    assert_eq!(alice.unwrap(), "1\n00:00:01,000 --> 00:00:04,000\nAlice: Welcome back to the show.\n");
+    assert!(!vtt);
+}
+
+#[test]
+fn hls_stitches_like_the_library() {
+    let output = mergevtt(&["--hls", "tests/fixtures/hls-segment-1.vtt", "tests/fixtures/hls-segment-2.vtt"]);
+    let segments = ["hls-segment-1.vtt", "hls-segment-2.vtt"]
+        .map(|name| mergevtt::WebVTT::from(&fixture(name)).unwrap())
+        .to_vec();
+    assert_eq!(output, mergevtt::WebVTT::concat_segments(segments).unwrap().render());
+}
+
+#[test]
+fn stats_count_overlaps_in_time_order() {
+    let stats = |sort_by| String::from_utf8(run(&["--no-speaker", "--stats", "--sort-by", sort_by, "tests/fixtures/speakers.vtt"]).stderr).unwrap();
+    assert!(stats("start").contains("overlaps: 1\n"));
+    assert!(stats("speaker").contains("overlaps: 1\n"), "{}", stats("speaker"));
+}
+
+#[test]
+fn no_validate_accepts_a_cue_right_after_the_header() {
+    let input = "tests/fixtures/no-blank-line.vtt";
+    let strict = Command::new(env!("CARGO_BIN_EXE_mergevtt"))
+        .current_dir(env!("CARGO_MANIFEST_DIR"))
+        .args(["--no-speaker", input])
+        .output()
+        .unwrap();
+    assert!(!strict.status.success());
+    let stderr = String::from_utf8(strict.stderr).unwrap();
+    assert!(stderr.contains("line 2: parsing error: expected a blank line after the header"), "{stderr}");
+    let output = mergevtt(&["--no-speaker", "--no-validate", input]);
+    assert_eq!(output, "WEBVTT\n\n00:00:01.000 --> 00:00:02.000\nhi\n");
+}
+
+#[test]
+fn check_follows_no_validate() {
+    let input = "tests/fixtures/end-before-start.vtt";
+    let strict = Command::new(env!("CARGO_BIN_EXE_mergevtt"))
+        .current_dir(env!("CARGO_MANIFEST_DIR"))
+        .args(["--check", input])
+        .output()
+        .unwrap();
+    assert!(!strict.status.success());
+    run(&["--check", "--no-validate", input]);
+}
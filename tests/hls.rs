@@ -0,0 +1,41 @@
+mod common;
+
+use common::fixture;
+use mergevtt::WebVTT;
+
+#[test]
+fn timestamp_map_is_kept() {
+    let input = fixture("hls-segment.vtt");
+    assert_eq!(WebVTT::from(&input).unwrap().render(), input);
+}
+
+#[test]
+fn timestamp_map_is_applied() {
+    let mut vtt = WebVTT::from(&fixture("hls-segment.vtt")).unwrap();
+    assert!(vtt.apply_timestamp_map().unwrap());
+    // 181083 ticks of 90kHz are 2.012s
+    let starts: Vec<_> = vtt.cues().map(|c| c.timerange().start().to_string()).collect();
+    assert_eq!(starts, ["00:00:02.012", "00:00:04.014"]);
+    assert!(!vtt.render().contains("X-TIMESTAMP-MAP"));
+    assert!(!vtt.apply_timestamp_map().unwrap());
+}
+
+#[test]
+fn segments_are_stitched() {
+    let segments = ["hls-segment-1.vtt", "hls-segment-2.vtt"]
+        .map(|name| WebVTT::from(&fixture(name)).unwrap())
+        .to_vec();
+    let vtt = WebVTT::concat_segments(segments).unwrap();
+    assert_eq!(vtt.render(), "\
+WEBVTT
+
+00:00:10.000 --> 00:00:13.000
+Good evening.
+
+00:00:15.000 --> 00:00:17.000
+Our top story tonight
+
+00:00:17.500 --> 00:00:19.000
+is the weather.
+");
+}
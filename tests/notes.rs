@@ -0,0 +1,39 @@
+mod common;
+
+use common::fixture;
+use mergevtt::WebVTT;
+
+fn files() -> Vec<WebVTT> {
+    ["notes-alice.vtt", "notes-bob.vtt"]
+        .map(|name| WebVTT::from(&fixture(name)).unwrap())
+        .to_vec()
+}
+
+#[test]
+fn notes_stay_with_their_cue() {
+    let merged = WebVTT::merge_all(files());
+    let notes: Vec<_> = merged.cues().map(|c| (c.text(), c.notes())).collect();
+    assert_eq!(notes, [
+        ("two", &["NOTE keep me".to_owned()][..]),
+        ("one", &["NOTE from alice".to_owned()][..]),
+        ("three", &["NOTE after bob".to_owned()][..]),
+    ]);
+}
+
+#[test]
+fn notes_of_dropped_cues_move_on() {
+    let mut merged = WebVTT::merge_all(files());
+    merged.retain_speakers(&["Alice".to_owned()]);
+    assert_eq!(merged.render(), "\
+WEBVTT
+
+NOTE after bob
+
+NOTE keep me
+
+NOTE from alice
+
+00:01.000 --> 00:02.000
+<v Alice>one
+");
+}
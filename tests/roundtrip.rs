@@ -0,0 +1,109 @@
+mod common;
+
+use common::fixture;
+use mergevtt::{RenderOptions, WebVTT};
+
+/// Printing a parsed file gives back the file, and parsing that again gives
+/// the same cues.
+fn assert_roundtrip(name: &str) {
+    let input = fixture(name);
+    let vtt = WebVTT::from(&input).unwrap();
+    let output = vtt.render();
+    assert_eq!(output, input, "{name}");
+    assert_eq!(WebVTT::from(&output).unwrap(), vtt, "{name}");
+}
+
+#[test]
+fn identifiers() {
+    assert_roundtrip("identifiers.vtt");
+    let vtt = WebVTT::from(&fixture("identifiers.vtt")).unwrap();
+    let identifiers: Vec<_> = vtt.cues().map(|c| c.identifier()).collect();
+    assert_eq!(identifiers, [Some("intro"), Some("2")]);
+}
+
+#[test]
+fn multiline() {
+    assert_roundtrip("multiline.vtt");
+    let vtt = WebVTT::from(&fixture("multiline.vtt")).unwrap();
+    let first = vtt.cues().next().unwrap();
+    assert_eq!(first.speaker(), Some("Alice"));
+    assert_eq!(first.text().lines().count(), 3);
+}
+
+#[test]
+fn settings() {
+    assert_roundtrip("settings.vtt");
+    let vtt = WebVTT::from(&fixture("settings.vtt")).unwrap();
+    let settings: Vec<_> = vtt.cues().map(|c| c.settings()).collect();
+    assert_eq!(settings, [Some("align:start position:10% region:fred"), Some("line:0 vertical:rl")]);
+    assert_eq!(vtt.cues().next().unwrap().speaker(), Some("Narrator & Co"));
+}
+
+#[test]
+fn write_to_matches_render() {
+    for name in ["identifiers.vtt", "multiline.vtt", "settings.vtt"] {
+        let vtt = WebVTT::from(&fixture(name)).unwrap();
+        let mut written = vec![];
+        vtt.write_to(&mut written).unwrap();
+        assert_eq!(String::from_utf8(written).unwrap(), vtt.render(), "{name}");
+    }
+}
+
+#[test]
+fn arrows_in_text() {
+    assert_roundtrip("arrows.vtt");
+    let vtt = WebVTT::from(&fixture("arrows.vtt")).unwrap();
+    let texts: Vec<_> = vtt.cues().map(|c| c.text()).collect();
+    assert_eq!(texts, ["press the --> key", "first line\na --> b --> c"]);
+    // an arrow always makes it a cue, so this is a broken timing, not a note
+    assert!(WebVTT::from("WEBVTT\n\nNOTE --> x\n").is_err());
+}
+
+/// JSON read back, written as WebVTT and parsed again gives the same JSON.
+#[cfg(feature = "serde")]
+#[test]
+fn json_roundtrip() {
+    for name in ["identifiers.vtt", "multiline.vtt", "settings.vtt", "chapters.vtt"] {
+        let json = serde_json::to_string(&WebVTT::from(&fixture(name)).unwrap()).unwrap();
+        let vtt = WebVTT::from(&WebVTT::from_json(&json).unwrap().render()).unwrap();
+        assert_eq!(serde_json::to_string(&vtt).unwrap(), json, "{name}");
+    }
+}
+
+#[test]
+fn sloppy_timings_are_normalized() {
+    let input = fixture("sloppy-timings.vtt");
+    let mut vtt = WebVTT::from(&input).unwrap();
+    let output = vtt.render();
+    assert_eq!(output, "\
+WEBVTT
+
+00:00:01.500 --> 00:00:02.500
+one
+
+00:00:03.000 --> 00:00:04.000
+two
+
+00:00:05.000 --> 00:00:06.000 align:start
+three
+");
+    assert_eq!(WebVTT::from(&output).unwrap(), vtt);
+    vtt.set_render_options(RenderOptions { keep_timing: true, ..RenderOptions::default() });
+    assert_eq!(vtt.render(), input);
+}
+
+#[test]
+fn crlf_parses_like_lf() {
+    let crlf = WebVTT::from(&fixture("crlf.vtt")).unwrap();
+    assert_eq!(crlf, WebVTT::from(&fixture("multiline.vtt")).unwrap());
+    assert_eq!(crlf.render(), fixture("multiline.vtt"));
+}
+
+#[test]
+fn whitespace_lines() {
+    let vtt = WebVTT::from(&fixture("whitespace-lines.vtt")).unwrap();
+    let texts: Vec<_> = vtt.cues().map(|c| c.text()).collect();
+    // only an empty line ends a cue, lines of spaces between cues are skipped
+    assert_eq!(texts, ["first line\n   \nstill the same cue", "after a separator with spaces"]);
+    assert_eq!(WebVTT::from(&vtt.render()).unwrap(), vtt);
+}